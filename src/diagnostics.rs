@@ -0,0 +1,267 @@
+use crate::metrics::Metrics;
+use crate::utils::get_save_path;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use rosc::OscPacket;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Number of OSC packets kept for the diagnostics report.
+const OSC_LOG_CAPACITY: usize = 100;
+/// Number of avatar swaps kept in `avatar_swaps.json`.
+const AVATAR_SWAP_CAPACITY: usize = 50;
+/// Settings included in the diagnostics report.
+const CONFIG_KEYS: &[&str] = &[
+    "RUST_LOG",
+    "OSCQuery",
+    "OSCQUERY_TIMEOUT_MS",
+    "PORT",
+    "VRC_IP",
+    "VRC_PORT",
+    "RECV_BUFFER_SIZE",
+    "MIRROR_ADDRESS",
+    "RECEIVE_ONLY",
+    "SEND_INTERVAL_SECS",
+    "SMOOTH_STEPS",
+    "SEND_NEXT_CHILD",
+    "AVATAR_POLL_SECS",
+    "AVATAR_CLEAR_GRACE_MS",
+    "AVATAR_CHANGE_DEBOUNCE_MS",
+    "SEND_PROGRESS_BUNDLE",
+    "DEBUG",
+    "INVARIANTS_PANIC",
+    "DISPLAY_UNITS",
+    "CALENDAR",
+    "MONTH_DAYS",
+    "YEAR_DAYS",
+    "AUTOSAVE_SECS",
+    "AUTO_RESET_ON_LOAD",
+    "APP_ACTIVE_HEARTBEAT",
+    "APP_ACTIVE_SECS",
+    "BUFFER_INACTIVE",
+    "MIN_CHILDREN_TO_START",
+    "CONCURRENT_PREGNANCIES",
+    "OVERDUE_GROWTH_CAP",
+    "METRICS_ADDRESS",
+    "METRICS_SECS",
+    "LABEL_ADDRESS",
+    "LABEL_SECS",
+    "DISCORD_PRESENCE",
+    "DISCORD_CLIENT_ID",
+    "LOCAL_API",
+    "LOCAL_API_PORT",
+    "NOTIFICATIONS",
+    "NOTIFY_TRIMESTER",
+    "NOTIFY_ALMOST_DUE",
+    "NOTIFY_BIRTH",
+];
+
+lazy_static! {
+    static ref OSC_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    /// Loaded from disk on first use.
+    static ref AVATAR_SWAPS: Mutex<Option<VecDeque<AvatarSwap>>> = Mutex::new(None);
+}
+
+/// One avatar change seen by `check_avatar_oscquery`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AvatarSwap {
+    pub time: DateTime<Local>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub fertility_detected: bool,
+}
+impl std::fmt::Display for AvatarSwap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {} (fertility system: {})",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.from.as_deref().unwrap_or("none"),
+            self.to.as_deref().unwrap_or("none"),
+            if self.fertility_detected { "yes" } else { "no" }
+        )
+    }
+}
+/// Appends `swap`, dropping the oldest entries beyond `capacity`.
+pub fn push_avatar_swap(log: &mut VecDeque<AvatarSwap>, swap: AvatarSwap, capacity: usize) {
+    log.push_back(swap);
+    while log.len() > capacity {
+        log.pop_front();
+    }
+}
+fn avatar_swap_path() -> std::path::PathBuf {
+    get_save_path().join("avatar_swaps.json")
+}
+fn load_avatar_swaps() -> VecDeque<AvatarSwap> {
+    std::fs::read_to_string(avatar_swap_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+/// Adds an avatar swap to the persisted log.
+pub fn record_avatar_swap(swap: AvatarSwap) {
+    let mut swaps = AVATAR_SWAPS.lock().unwrap();
+    let log = swaps.get_or_insert_with(load_avatar_swaps);
+    push_avatar_swap(log, swap, AVATAR_SWAP_CAPACITY);
+    let result = serde_json::to_string_pretty(&*log)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(avatar_swap_path(), json));
+    if let Err(e) = result {
+        log::error!("Failed to write avatar swap log: {}", e);
+    }
+}
+/// Logged avatar swaps, oldest first.
+pub fn avatar_swaps() -> Vec<AvatarSwap> {
+    let mut swaps = AVATAR_SWAPS.lock().unwrap();
+    swaps.get_or_insert_with(load_avatar_swaps).iter().cloned().collect()
+}
+
+/// Adds a packet to the recent OSC log, dropping the oldest entry when full.
+/// `direction` is `"in"` or `"out"`.
+pub fn record_osc(direction: &str, packet: &OscPacket) {
+    let mut log = OSC_LOG.lock().unwrap();
+    if log.len() >= OSC_LOG_CAPACITY {
+        log.pop_front();
+    }
+    log.push_back(format!(
+        "{} {} {:?}",
+        Local::now().format("%H:%M:%S"),
+        direction,
+        packet
+    ));
+}
+pub fn recent_osc() -> Vec<String> {
+    OSC_LOG.lock().unwrap().iter().cloned().collect()
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    ["TOKEN", "SECRET", "PASSWORD", "KEY"]
+        .iter()
+        .any(|marker| key.contains(marker))
+}
+/// Current values of the known settings, with anything secret-looking redacted.
+pub fn config_snapshot() -> Vec<(String, String)> {
+    CONFIG_KEYS
+        .iter()
+        .map(|key| {
+            let value = match std::env::var(key) {
+                Ok(_) if is_secret(key) => "<redacted>".to_string(),
+                Ok(value) => value,
+                Err(_) => "<unset>".to_string(),
+            };
+            (key.to_string(), value)
+        })
+        .collect()
+}
+
+/// Assembles the report shared in bug reports. Each part starts with a `[Section]` header.
+pub fn build_diagnostics(
+    state_json: &str,
+    recent_osc: &[String],
+    avatar_swaps: &[AvatarSwap],
+    metrics: &Metrics,
+    config: &[(String, String)],
+) -> String {
+    let mut report = String::from("== Pregnancy OSC Diagnostics ==\n");
+    report.push_str(&format!("\n[Version]\n{}\n", env!("CARGO_PKG_VERSION")));
+    report.push_str(&format!("\n[State]\n{}\n", state_json));
+    report.push_str(&format!(
+        "\n[Counters]\npackets_received: {}\npackets_sent: {}\nhttp_fetches: {}\nlast_avatar_swap: {}\n",
+        metrics.packets_received,
+        metrics.packets_sent,
+        metrics.http_fetches,
+        metrics
+            .last_avatar_swap
+            .map_or("never".to_string(), |swap| swap.to_rfc3339())
+    ));
+    report.push_str("\n[Config]\n");
+    for (key, value) in config {
+        report.push_str(&format!("{}={}\n", key, value));
+    }
+    report.push_str("\n[Avatar Swaps]\n");
+    for swap in avatar_swaps {
+        report.push_str(&format!("{}\n", swap));
+    }
+    report.push_str("\n[Recent OSC]\n");
+    for line in recent_osc {
+        report.push_str(line);
+        report.push('\n');
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::lock_globals;
+    use chrono::TimeZone;
+
+    #[test]
+    fn report_has_every_section_in_order() {
+        let swap = AvatarSwap {
+            time: Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+            from: None,
+            to: Some("avtr_b".to_string()),
+            fertility_detected: true,
+        };
+        let report = build_diagnostics(
+            r#"{"active":true}"#,
+            &["12:00:00 in packet".to_string()],
+            &[swap],
+            &Metrics::default(),
+            &[("PORT".to_string(), "9001".to_string())],
+        );
+        let headers = ["[Version]", "[State]", "[Counters]", "[Config]", "[Avatar Swaps]", "[Recent OSC]"];
+        let positions: Vec<usize> = headers
+            .iter()
+            .map(|header| report.find(header).unwrap_or_else(|| panic!("missing {}", header)))
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(report.contains(r#"{"active":true}"#));
+        assert!(report.contains("last_avatar_swap: never"));
+        assert!(report.contains("PORT=9001"));
+        assert!(report.contains("2024-01-01 12:00:00 none -> avtr_b (fertility system: yes)"));
+        assert!(report.ends_with("12:00:00 in packet\n"));
+    }
+
+    #[test]
+    fn secret_looking_keys_are_detected() {
+        assert!(is_secret("DISCORD_TOKEN"));
+        assert!(is_secret("api_key"));
+        assert!(!is_secret("DISCORD_CLIENT_ID"));
+        assert!(!is_secret("PORT"));
+    }
+
+    #[test]
+    fn osc_log_keeps_the_latest_packets() {
+        let _guard = lock_globals();
+        for index in 0..OSC_LOG_CAPACITY + 5 {
+            let packet = OscPacket::Message(rosc::OscMessage {
+                addr: format!("/test/{}", index),
+                args: Vec::new(),
+            });
+            record_osc("in", &packet);
+        }
+        let log = recent_osc();
+        assert_eq!(log.len(), OSC_LOG_CAPACITY);
+        assert!(log[0].contains("\"/test/5\""));
+        assert!(log.last().unwrap().contains(&format!("\"/test/{}\"", OSC_LOG_CAPACITY + 4)));
+    }
+
+    #[test]
+    fn avatar_swap_log_is_bounded() {
+        let swap = |to: &str| AvatarSwap {
+            time: Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            from: None,
+            to: Some(to.to_string()),
+            fertility_detected: false,
+        };
+        let mut log = VecDeque::new();
+        for to in ["avtr_1", "avtr_2", "avtr_3"] {
+            push_avatar_swap(&mut log, swap(to), 2);
+        }
+        assert_eq!(log, [swap("avtr_2"), swap("avtr_3")]);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::pregancy_handler::format_duration_human;
+use crate::utils::env_or;
+use discord_rich_presence::{activity::Activity, DiscordIpc, DiscordIpcClient};
+use lazy_static::lazy_static;
+use log::debug;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref ENABLED: Mutex<bool> = Mutex::new(env_or("DISCORD_PRESENCE", false));
+    static ref PRESENCE: Mutex<Presence> = Mutex::new(Presence::new());
+}
+
+pub fn is_enabled() -> bool {
+    *ENABLED.lock().unwrap()
+}
+pub fn set_enabled(value: bool) {
+    *ENABLED.lock().unwrap() = value;
+}
+/// Shows `text` through the shared connection, or clears the presence for `None`.
+pub fn update(text: Option<(String, String)>) {
+    PRESENCE.lock().unwrap().update(text);
+}
+/// Clears the presence so it does not outlive the app.
+pub fn clear() {
+    PRESENCE.lock().unwrap().update(None);
+}
+
+/// Builds the (details, state) lines shown in Discord, or `None` when not pregnant.
+pub fn presence_text(
+    child_count: u8,
+    progress: f64,
+    remaining: Option<chrono::Duration>,
+) -> Option<(String, String)> {
+    if child_count == 0 {
+        return None;
+    }
+    let children = if child_count == 1 {
+        "1 child".to_string()
+    } else {
+        format!("{} children", child_count)
+    };
+    let details = format!("Pregnant with {} ({:.0}%)", children, progress * 100.0);
+    let state = match remaining {
+        Some(remaining) if remaining > chrono::Duration::zero() => {
+            format!("Due in {}", format_duration_human(remaining))
+        }
+        Some(_) => "Overdue".to_string(),
+        None => "Due date unknown".to_string(),
+    };
+    Some((details, state))
+}
+
+/// Discord IPC connection that is opened lazily and silently skipped when Discord is not running.
+pub struct Presence {
+    client: Option<DiscordIpcClient>,
+    shown: Option<(String, String)>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Presence {
+            client: None,
+            shown: None,
+        }
+    }
+
+    /// Shows `text`, or clears the presence for `None`. Unchanged text is not resent.
+    pub fn update(&mut self, text: Option<(String, String)>) {
+        if text == self.shown {
+            return;
+        }
+        let Some(client) = self.connect() else {
+            return;
+        };
+        let result = match &text {
+            Some((details, state)) => client.set_activity(
+                Activity::new()
+                    .details(details.as_str())
+                    .state(state.as_str()),
+            ),
+            None => client.clear_activity(),
+        };
+        match result {
+            Ok(()) => self.shown = text,
+            Err(e) => {
+                debug!("Discord presence update failed: {}", e);
+                // Reconnect on the next update, e.g. after Discord restarts.
+                self.client = None;
+            }
+        }
+    }
+
+    fn connect(&mut self) -> Option<&mut DiscordIpcClient> {
+        if self.client.is_none() {
+            let client_id = std::env::var("DISCORD_CLIENT_ID").ok()?;
+            let mut client = DiscordIpcClient::new(client_id);
+            if let Err(e) = client.connect() {
+                debug!("Discord is not available: {}", e);
+                return None;
+            }
+            self.client = Some(client);
+        }
+        self.client.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn nothing_is_shown_without_children() {
+        assert_eq!(presence_text(0, 0.5, Some(Duration::hours(1))), None);
+    }
+
+    #[test]
+    fn presence_lines_follow_the_pregnancy() {
+        assert_eq!(
+            presence_text(1, 0.254, Some(Duration::hours(3))),
+            Some(("Pregnant with 1 child (25%)".to_string(), "Due in 3 hours".to_string()))
+        );
+        assert_eq!(
+            presence_text(3, 1.0, Some(Duration::zero())),
+            Some(("Pregnant with 3 children (100%)".to_string(), "Overdue".to_string()))
+        );
+        assert_eq!(
+            presence_text(2, 0.0, None).map(|(_, state)| state),
+            Some("Due date unknown".to_string())
+        );
+    }
+}
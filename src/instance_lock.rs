@@ -0,0 +1,76 @@
+use log::warn;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Guard that keeps other copies of the app from running at the same time.
+/// The OS releases the file lock when the process exits, even after a crash,
+/// so a leftover lock file on its own never blocks startup.
+pub struct InstanceLock {
+    file: File,
+}
+
+impl InstanceLock {
+    /// Tries to take the lock at `path`.
+    /// Returns `Ok(None)` when another running instance already holds it.
+    pub fn acquire(path: &Path) -> std::io::Result<Option<InstanceLock>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        // A pid left in an unlocked file belongs to an instance that did not shut down cleanly.
+        let mut previous = String::new();
+        if file.read_to_string(&mut previous).is_ok() && !previous.trim().is_empty() {
+            warn!(
+                "Replacing stale instance lock left by process {}",
+                previous.trim()
+            );
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Some(InstanceLock { file }))
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // Clear our pid so a clean exit is not reported as stale next launch.
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_dir;
+
+    #[test]
+    fn second_acquire_is_refused_until_released() {
+        let path = test_dir("instance-lock").join("instance.lock");
+        let lock = InstanceLock::acquire(&path).unwrap();
+        assert!(lock.is_some());
+        assert!(InstanceLock::acquire(&path).unwrap().is_none());
+        drop(lock);
+        assert!(InstanceLock::acquire(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn stale_lock_file_is_taken_over() {
+        let path = test_dir("instance-lock-stale").join("instance.lock");
+        std::fs::write(&path, "99999").unwrap();
+        let lock = InstanceLock::acquire(&path).unwrap();
+        assert!(lock.is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), std::process::id().to_string());
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::utils::env_or;
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref ENABLED: Mutex<bool> = Mutex::new(env_or("LOCAL_API", false));
+    /// Set once the listener is running, or holds why it could not start.
+    static ref LISTENER: Mutex<Option<Result<u16, String>>> = Mutex::new(None);
+}
+
+pub fn is_enabled() -> bool {
+    *ENABLED.lock().unwrap()
+}
+/// Turns the API on or off. The listener is started the first time it is enabled
+/// and answers 503 while disabled.
+pub fn set_enabled(value: bool, status: fn() -> serde_json::Value) {
+    *ENABLED.lock().unwrap() = value;
+    if value {
+        ensure_started(status);
+    }
+}
+/// Port the API listens on, or why it is not listening.
+pub fn listener_state() -> Option<Result<u16, String>> {
+    LISTENER.lock().unwrap().clone()
+}
+
+/// Binds `LOCAL_API_PORT` on localhost unless that was already done.
+/// A port that is in use is reported instead of stopping the app.
+fn ensure_started(status: fn() -> serde_json::Value) {
+    let mut listener_state = LISTENER.lock().unwrap();
+    if matches!(*listener_state, Some(Ok(_))) {
+        return;
+    }
+    let port = env_or("LOCAL_API_PORT", 9333u16);
+    match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+        Ok(listener) => {
+            info!("Local API listening on http://127.0.0.1:{}/status", port);
+            *listener_state = Some(Ok(port));
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if let Err(e) = serve(stream, status) {
+                        debug!("Local API request failed: {}", e);
+                    }
+                }
+            });
+        }
+        Err(e) => {
+            error!("Local API could not listen on port {}: {}", port, e);
+            *listener_state = Some(Err(format!("Port {} unavailable: {}", port, e)));
+        }
+    }
+}
+
+fn serve(stream: TcpStream, status: fn() -> serde_json::Value) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status_line, body) = route(&request_line, is_enabled(), status);
+    let mut stream = stream;
+    stream.write_all(http_response(status_line, &body).as_bytes())
+}
+
+/// Picks the response for an HTTP request line such as `GET /status HTTP/1.1`.
+fn route(
+    request_line: &str,
+    enabled: bool,
+    status: fn() -> serde_json::Value,
+) -> (&'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    if !enabled {
+        return ("503 Service Unavailable", r#"{"error":"disabled"}"#.to_string());
+    }
+    match (method, path) {
+        (Some("GET"), Some("/") | Some("/status")) => ("200 OK", status().to_string()),
+        (Some("GET"), _) => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+        _ => ("405 Method Not Allowed", r#"{"error":"method not allowed"}"#.to_string()),
+    }
+}
+
+fn http_response(status_line: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::lock_globals;
+    use std::io::Read;
+
+    fn status() -> serde_json::Value {
+        serde_json::json!({ "avatar_id": "avtr_a", "child_count": 2, "progress": 0.5 })
+    }
+
+    #[test]
+    fn routes_answer_by_method_and_path() {
+        assert_eq!(route("GET /status HTTP/1.1", true, status), ("200 OK", status().to_string()));
+        assert_eq!(route("GET / HTTP/1.1", true, status).0, "200 OK");
+        assert_eq!(route("GET /other HTTP/1.1", true, status).0, "404 Not Found");
+        assert_eq!(route("POST /status HTTP/1.1", true, status).0, "405 Method Not Allowed");
+        assert_eq!(route("", true, status).0, "405 Method Not Allowed");
+        assert_eq!(route("GET /status HTTP/1.1", false, status).0, "503 Service Unavailable");
+    }
+
+    #[test]
+    fn served_json_is_the_status() {
+        let _guard = lock_globals();
+        let enabled = is_enabled();
+        *ENABLED.lock().unwrap() = true;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, status).unwrap();
+        *ENABLED.lock().unwrap() = enabled;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert_eq!(serde_json::from_str::<serde_json::Value>(body).unwrap(), status());
+    }
+}
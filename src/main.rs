@@ -1,4 +1,4 @@
-use std::{env, u16,net::IpAddr, vec};
+use std::{env, vec};
 
 mod osc_server;
 use osc_server::{OscServer, PacketHandler};
@@ -8,13 +8,48 @@ use eframe::egui;
 mod osc_query_cache;
 use dotenv::dotenv;
 
-use log::info;
+use log::{error, info};
 use std::sync::Arc;
 use vrchat_osc::{Error};
 mod utils;
+mod instance_lock;
+mod metrics;
+mod diagnostics;
+mod settings;
+#[cfg(feature = "discord")]
+mod discord_presence;
+#[cfg(feature = "notifications")]
+mod notifications;
+#[cfg(feature = "local_api")]
+mod local_api;
+use instance_lock::InstanceLock;
 
 
 fn main() -> eframe::Result<()> {
+    dotenv().ok();
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info,vrchat_osc=warn,")
+    }
+    env_logger::init();
+    if env::args().any(|arg| arg == "--selftest") {
+        let steps = pregancy_handler::run_selftest();
+        for step in &steps {
+            println!("[{}] {}", if step.passed { "PASS" } else { "FAIL" }, step.name);
+        }
+        std::process::exit(if steps.iter().all(|step| step.passed) { 0 } else { 1 });
+    }
+    // Two copies would fight over the OSC ports and the save file, so only one may run.
+    let _instance_lock = match InstanceLock::acquire(&utils::get_save_path().join("instance.lock")) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            error!("Pregnancy Monitor is already running. Close the other window before starting a new one.");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("Failed to create instance lock: {}", e);
+            std::process::exit(1);
+        }
+    };
     // Spawn async OSC setup in a separate thread
     std::thread::spawn(|| {
         let _ = tokio::runtime::Builder::new_current_thread()
@@ -34,14 +69,11 @@ fn main() -> eframe::Result<()> {
         ..Default::default()
     };
 
-    eframe::run_native("Pregnancy Monitor", options, Box::new(|_cc| Ok(Box::new(PregUI::new(_cc)))))
+    let result = eframe::run_native("Pregnancy Monitor", options, Box::new(|_cc| Ok(Box::new(PregUI::new(_cc)))));
+    pregancy_handler::shutdown();
+    result
 }
 async fn async_main() -> Result<(), Error> {
-    dotenv().ok();
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info,vrchat_osc=warn,")
-    }
-    env_logger::init();
     let osc_query_enabled = env::var("OSCQuery")
         .unwrap_or("true".to_string())
         .parse::<bool>()
@@ -51,26 +83,16 @@ async fn async_main() -> Result<(), Error> {
         OscServer::packet_handler(handlers).await;
         info!("OSCQuery Enabled and started.");
     } else {
-        let portnumber: u16 = env::var("PORT")
-            .unwrap_or("0".to_string())
-            .parse()
-            .expect("PORT must be a valid u16");
-        OscServer::start("0.0.0.0", portnumber, handlers);
-        info!(
-            "OSC Server started on port {}",
-            OscServer::get_osc_port().unwrap()
-        );
-        let vrc_osc: IpAddr = env::var("VRC_IP")
-            .unwrap_or("127.0.0.1".to_string())
-            .parse()
-            .expect("address must be a valid IP address");
-        let vrc_port: u16 = env::var("VRC_PORT")
-            .unwrap_or("9000".to_string())
-            .parse()
-            .expect("vrc_port must be a valid u16");
-        OscServer::set_vrc_address(vrc_osc, vrc_port);
+        OscServer::start_udp_from_env(handlers);
+    }
+    let metrics_address = env::var("METRICS_ADDRESS").unwrap_or_default();
+    if !metrics_address.is_empty() {
+        let metrics_secs = utils::env_or("METRICS_SECS", 10u64);
+        metrics::spawn_emitter(metrics_address, std::time::Duration::from_secs(metrics_secs));
     }
     tokio::signal::ctrl_c().await?;
-
-    Ok(())
+    // Handling Ctrl+C replaces the default exit, so save and quit here.
+    info!("Shutting down");
+    pregancy_handler::shutdown();
+    std::process::exit(0);
 }
@@ -0,0 +1,120 @@
+use crate::osc_server::OscServer;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use rosc::{OscMessage, OscType};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Internal counters, kept for diagnostics and optional OSC emission.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    pub packets_received: u64,
+    pub packets_sent: u64,
+    pub http_fetches: u64,
+    pub last_avatar_swap: Option<DateTime<Local>>,
+}
+
+lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+}
+
+pub fn record_packet_received() {
+    METRICS.lock().unwrap().packets_received += 1;
+}
+pub fn record_packet_sent() {
+    METRICS.lock().unwrap().packets_sent += 1;
+}
+pub fn record_http_fetch() {
+    METRICS.lock().unwrap().http_fetches += 1;
+}
+pub fn record_avatar_swap() {
+    METRICS.lock().unwrap().last_avatar_swap = Some(Local::now());
+}
+pub fn snapshot() -> Metrics {
+    METRICS.lock().unwrap().clone()
+}
+
+/// Builds the metric messages under `prefix`, in this order (all `Long`):
+/// `packets_received`, `packets_sent`, `http_fetches`, and `last_avatar_swap`
+/// as unix seconds (0 when no swap has been seen).
+pub fn metrics_messages(prefix: &str, metrics: &Metrics) -> Vec<OscMessage> {
+    let prefix = prefix.trim_end_matches('/');
+    let long = |value: u64| OscType::Long(i64::try_from(value).unwrap_or(i64::MAX));
+    vec![
+        OscMessage {
+            addr: format!("{}/packets_received", prefix),
+            args: vec![long(metrics.packets_received)],
+        },
+        OscMessage {
+            addr: format!("{}/packets_sent", prefix),
+            args: vec![long(metrics.packets_sent)],
+        },
+        OscMessage {
+            addr: format!("{}/http_fetches", prefix),
+            args: vec![long(metrics.http_fetches)],
+        },
+        OscMessage {
+            addr: format!("{}/last_avatar_swap", prefix),
+            args: vec![OscType::Long(
+                metrics.last_avatar_swap.map_or(0, |swap| swap.timestamp()),
+            )],
+        },
+    ]
+}
+
+/// Sends the metrics to `prefix` every `interval` (never faster than once a second).
+pub fn spawn_emitter(prefix: String, interval: Duration) {
+    let interval = interval.max(Duration::from_secs(1));
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        for message in metrics_messages(&prefix, &snapshot()) {
+            OscServer::send_osc_data(message.addr, message.args);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn messages_follow_the_documented_order() {
+        let metrics = Metrics {
+            packets_received: 3,
+            packets_sent: 5,
+            http_fetches: u64::MAX,
+            last_avatar_swap: Some(Local.timestamp_opt(1_700_000_000, 0).unwrap()),
+        };
+        let messages = metrics_messages("/metrics/", &metrics);
+        let sent: Vec<(&str, &OscType)> = messages.iter().map(|msg| (msg.addr.as_str(), &msg.args[0])).collect();
+        assert_eq!(
+            sent,
+            [
+                ("/metrics/packets_received", &OscType::Long(3)),
+                ("/metrics/packets_sent", &OscType::Long(5)),
+                ("/metrics/http_fetches", &OscType::Long(i64::MAX)),
+                ("/metrics/last_avatar_swap", &OscType::Long(1_700_000_000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_swap_is_sent_as_zero() {
+        let messages = metrics_messages("/m", &Metrics::default());
+        assert_eq!(messages[3].addr, "/m/last_avatar_swap");
+        assert_eq!(messages[3].args, [OscType::Long(0)]);
+    }
+
+    #[test]
+    fn counters_only_grow() {
+        let before = snapshot();
+        record_packet_received();
+        record_packet_sent();
+        record_http_fetch();
+        let after = snapshot();
+        assert!(after.packets_received > before.packets_received);
+        assert!(after.packets_sent > before.packets_sent);
+        assert!(after.http_fetches > before.http_fetches);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::utils::env_or;
+use lazy_static::lazy_static;
+use log::debug;
+use notify_rust::Notification;
+use std::sync::Mutex;
+
+/// Points in a pregnancy that can raise a desktop notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Milestone {
+    SecondTrimester,
+    ThirdTrimester,
+    AlmostDue,
+    Birth,
+}
+impl Milestone {
+    const ALL: [Milestone; 4] = [
+        Milestone::SecondTrimester,
+        Milestone::ThirdTrimester,
+        Milestone::AlmostDue,
+        Milestone::Birth,
+    ];
+    /// Progress fraction at which the milestone is reached.
+    fn threshold(self) -> f64 {
+        match self {
+            Milestone::SecondTrimester => 1.0 / 3.0,
+            Milestone::ThirdTrimester => 2.0 / 3.0,
+            Milestone::AlmostDue => 0.95,
+            Milestone::Birth => 1.0,
+        }
+    }
+    fn message(self) -> &'static str {
+        match self {
+            Milestone::SecondTrimester => "Second trimester has started",
+            Milestone::ThirdTrimester => "Third trimester has started",
+            Milestone::AlmostDue => "Almost due",
+            Milestone::Birth => "Due date reached",
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            Milestone::SecondTrimester => "Second Trimester",
+            Milestone::ThirdTrimester => "Third Trimester",
+            Milestone::AlmostDue => "Almost Due",
+            Milestone::Birth => "Birth",
+        }
+    }
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+lazy_static! {
+    static ref ENABLED: Mutex<bool> = Mutex::new(env_or("NOTIFICATIONS", false));
+    static ref MILESTONES: Mutex<[bool; 4]> = Mutex::new([
+        env_or("NOTIFY_TRIMESTER", true),
+        env_or("NOTIFY_TRIMESTER", true),
+        env_or("NOTIFY_ALMOST_DUE", true),
+        env_or("NOTIFY_BIRTH", true),
+    ]);
+}
+
+pub fn is_enabled() -> bool {
+    *ENABLED.lock().unwrap()
+}
+pub fn set_enabled(value: bool) {
+    *ENABLED.lock().unwrap() = value;
+}
+pub fn milestones() -> [Milestone; 4] {
+    Milestone::ALL
+}
+pub fn is_milestone_enabled(milestone: Milestone) -> bool {
+    MILESTONES.lock().unwrap()[milestone.index()]
+}
+pub fn set_milestone_enabled(milestone: Milestone, value: bool) {
+    MILESTONES.lock().unwrap()[milestone.index()] = value;
+}
+
+/// Milestones crossed going from `last` to `current` progress.
+/// Nothing fires on the first reading, so restarting the app does not repeat old milestones,
+/// and a milestone fires again only after progress drops below it and crosses it anew.
+pub fn crossed_milestones(last: Option<f64>, current: f64) -> Vec<Milestone> {
+    let Some(last) = last else {
+        return Vec::new();
+    };
+    Milestone::ALL
+        .into_iter()
+        .filter(|milestone| last < milestone.threshold() && current >= milestone.threshold())
+        .collect()
+}
+
+/// Tracks progress between checks and shows a notification for each enabled milestone crossed.
+pub struct Notifier {
+    last_progress: Option<f64>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Notifier { last_progress: None }
+    }
+
+    /// Checks `progress`, or forgets the last reading for `None` (no pregnancy).
+    pub fn update(&mut self, progress: Option<f64>) {
+        let Some(progress) = progress else {
+            self.last_progress = None;
+            return;
+        };
+        let crossed = crossed_milestones(self.last_progress, progress);
+        self.last_progress = Some(progress);
+        if !is_enabled() {
+            return;
+        }
+        for milestone in crossed.into_iter().filter(|m| is_milestone_enabled(*m)) {
+            if let Err(e) = Notification::new()
+                .appname("Pregnancy Monitor")
+                .summary("Pregnancy Monitor")
+                .body(milestone.message())
+                .show()
+            {
+                debug!("Failed to show notification: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Milestones fired over a series of progress readings, as `Notifier` would see them.
+    fn fired(readings: &[f64]) -> Vec<Milestone> {
+        let mut last = None;
+        let mut fired = Vec::new();
+        for &progress in readings {
+            fired.extend(crossed_milestones(last, progress));
+            last = Some(progress);
+        }
+        fired
+    }
+
+    #[test]
+    fn the_first_reading_fires_nothing() {
+        assert!(crossed_milestones(None, 1.0).is_empty());
+        assert!(fired(&[0.97]).is_empty());
+    }
+
+    #[test]
+    fn each_milestone_fires_once_per_crossing() {
+        let readings = [0.1, 0.33, 0.34, 0.35, 0.5, 0.7, 0.8, 0.95, 0.96, 1.0, 1.0, 1.0];
+        assert_eq!(
+            fired(&readings),
+            [Milestone::SecondTrimester, Milestone::ThirdTrimester, Milestone::AlmostDue, Milestone::Birth]
+        );
+    }
+
+    #[test]
+    fn a_jump_fires_every_milestone_passed() {
+        assert_eq!(crossed_milestones(Some(0.5), 0.96), [Milestone::ThirdTrimester, Milestone::AlmostDue]);
+    }
+
+    #[test]
+    fn a_milestone_fires_again_only_after_dropping_below_it() {
+        assert_eq!(fired(&[0.9, 0.96, 0.94, 0.95]), [Milestone::AlmostDue, Milestone::AlmostDue]);
+        assert!(fired(&[0.96, 0.97, 0.96]).is_empty());
+    }
+}
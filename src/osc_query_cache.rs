@@ -1,48 +1,182 @@
+use crate::metrics;
 use crate::osc_server::OscServer;
+use crate::utils::env_or;
 use lazy_static::lazy_static;
 use serde_json::Value;
+#[cfg(test)]
+use std::collections::{HashMap, VecDeque};
+#[cfg(test)]
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
 
+/// Where the cache gets its OSCQuery data from.
+/// The live source talks to VRChat; tests can swap in a source that serves canned JSON.
+pub trait OscQuerySource: Send {
+    /// Base URL of the OSCQuery server, or `None` when it has not been discovered yet.
+    fn base_url(&self) -> Option<String>;
+    /// Fetches the body at `url`.
+    fn fetch(&self, url: &str) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Reads the discovered OSCQuery address from `OscServer` and fetches over HTTP.
+pub struct LiveOscQuery;
+
+impl OscQuerySource for LiveOscQuery {
+    fn base_url(&self) -> Option<String> {
+        OscServer::get_osc_query()
+    }
+
+    fn fetch(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        match Handle::try_current() {
+            // Already inside runtime → spawn task instead of blocking
+            Ok(handle) => {
+                // Use `block_in_place` to temporarily allow blocking inside async
+                tokio::task::block_in_place(|| {
+                    let rt = handle.clone();
+                    rt.block_on(async { fetch_avatar_data(url).await })
+                })
+            }
+
+            // Not inside runtime → use your global or local runtime
+            Err(_) => Tokio_RT.block_on(async { fetch_avatar_data(url).await }),
+        }
+    }
+}
+
+/// Builds the OSCQuery HTTP client. Requests give up after `timeout` so a hung
+/// VRChat server returns an error instead of freezing the caller (often the UI thread).
+fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .build()
+        .unwrap_or_else(|e| {
+            log::error!("Failed to build OSCQuery HTTP client, requests have no timeout: {}", e);
+            reqwest::Client::new()
+        })
+}
+/// Serves canned bodies per path (e.g. `/avatar/change`). Each path answers with its
+/// bodies in order and then keeps repeating the last one. Clones share the responses.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeOscQuery {
+    responses: Arc<Mutex<HashMap<String, VecDeque<String>>>>,
+    fetches: Arc<Mutex<Vec<String>>>,
+}
+#[cfg(test)]
+impl FakeOscQuery {
+    pub const BASE_URL: &'static str = "http://fake-oscquery";
+    /// Queues `body` as the next answer for `path`.
+    pub fn respond(&self, path: &str, body: &str) -> &Self {
+        let mut responses = self.responses.lock().unwrap();
+        responses.entry(path.to_string()).or_default().push_back(body.to_string());
+        self
+    }
+    /// Paths fetched so far, in order.
+    pub fn fetches(&self) -> Vec<String> {
+        self.fetches.lock().unwrap().clone()
+    }
+}
+#[cfg(test)]
+impl OscQuerySource for FakeOscQuery {
+    fn base_url(&self) -> Option<String> {
+        Some(Self::BASE_URL.to_string())
+    }
+
+    fn fetch(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let path = url.strip_prefix(Self::BASE_URL).unwrap_or(url);
+        self.fetches.lock().unwrap().push(path.to_string());
+        let mut responses = self.responses.lock().unwrap();
+        let queue = responses.get_mut(path).ok_or_else(|| format!("no response for {}", path))?;
+        match queue.len() {
+            0 => Err(format!("no response for {}", path).into()),
+            1 => Ok(queue[0].clone()),
+            _ => Ok(queue.pop_front().unwrap()),
+        }
+    }
+}
+// Common async block for both sync/async paths
+async fn fetch_avatar_data(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    fetch_with(&HTTP_CLIENT, url).await
+}
+async fn fetch_with(client: &reqwest::Client, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let resp = client.get(url).send().await?;
+    let resp = resp.error_for_status()?;
+    Ok(resp.text().await?)
+}
+
 pub struct OscQueryCache {
+    source: Box<dyn OscQuerySource>,
+    /// Time source, replaceable so cache expiry can be driven by hand.
+    clock: fn() -> Instant,
+    /// Minimum age of the cached data before `clear_avatar` drops it.
+    clear_grace: Duration,
     last_fetched: Option<Instant>,
     cached_data: Option<Value>,
     avatar_id: Option<String>,
+    avatar_id_fetched: Option<Instant>,
     avatar_name: Option<String>,
 }
+/// How long fetched OSCQuery data is reused before asking VRChat again.
+const CACHE_TTL: Duration = Duration::from_secs(5);
 lazy_static! {
     static ref CACHE: Mutex<OscQueryCache> = Mutex::new(OscQueryCache::new());
-    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
+    static ref HTTP_CLIENT: reqwest::Client = http_client(Duration::from_millis(env_or(
+        "OSCQUERY_TIMEOUT_MS",
+        2000u64
+    )));
     static ref Tokio_RT: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
 }
 
 impl OscQueryCache {
     pub fn new() -> Self {
+        Self::with_source(Box::new(LiveOscQuery))
+    }
+    pub fn with_source(source: Box<dyn OscQuerySource>) -> Self {
+        Self::with_source_and_clock(source, Instant::now)
+    }
+    /// Builds a cache whose TTL and clear window are measured against `clock`.
+    pub fn with_source_and_clock(source: Box<dyn OscQuerySource>, clock: fn() -> Instant) -> Self {
         Self {
+            source,
+            clock,
+            clear_grace: Duration::from_millis(env_or("AVATAR_CLEAR_GRACE_MS", 500u64)),
             last_fetched: None,
             cached_data: None,
             avatar_id: None,
+            avatar_id_fetched: None,
             avatar_name: None,
         }
     }
+    /// Debounced clear: drops the cached avatar only when the data is older than the grace
+    /// window, so data fetched moments ago (e.g. earlier in the same check) is kept.
     pub fn clear_avatar(&mut self) {
-        let now = Instant::now();
+        let now = (self.clock)();
         if let Some(timestamp) = &self.last_fetched {
-            if now.duration_since(*timestamp) > Duration::from_millis(500) {
-                self.avatar_id = None;
-                self.avatar_name = None;
-                self.cached_data = None;
+            if now.duration_since(*timestamp) > self.clear_grace {
+                self.force_clear_avatar();
                 self.last_fetched = Some(now);
             }
         }
     }
+    /// Drops all cached avatar data immediately, regardless of its age.
+    /// Used when VRChat reports an avatar change, so the next lookup always refetches.
+    pub fn force_clear_avatar(&mut self) {
+        self.avatar_id = None;
+        self.avatar_id_fetched = None;
+        self.avatar_name = None;
+        self.cached_data = None;
+        self.last_fetched = None;
+    }
 
-    // Common async block for both sync/async paths
-    async fn fetch_avatar_data(url: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let resp = HTTP_CLIENT.get(url).send().await?;
-        let resp = resp.error_for_status()?;
-        Ok(resp.text().await?)
+    fn fetch(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        metrics::record_http_fetch();
+        self.source.fetch(url).map_err(|e| {
+            log::error!("Failed to fetch avatar data from {}: {}", url, e);
+            e
+        })
     }
 
     pub fn get_avatar_id(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
@@ -50,76 +184,50 @@ impl OscQueryCache {
             //log::debug!("Returning cloned avatar id");
             return Ok(Some(avatar_id.clone()));
         }
-        let url = match OscServer::get_osc_query() {
+        let url = match self.source.base_url() {
             Some(base_url) => format!("{}/avatar/change", base_url),
             None => return Ok(None),
         };
 
-        // --- Core logic ---
-        let response = match Handle::try_current() {
-            // Already inside runtime → spawn task instead of blocking
-            Ok(handle) => {
-                // Use `block_in_place` to temporarily allow blocking inside async
-                tokio::task::block_in_place(|| {
-                    let rt = handle.clone();
-                    rt.block_on(async { OscQueryCache::fetch_avatar_data(&url).await })
-                })
-            }
-
-            // Not inside runtime → use your global or local runtime
-            Err(_) => Tokio_RT.block_on(async { OscQueryCache::fetch_avatar_data(&url).await }),
-        }
-        .map_err(|e: Box<dyn std::error::Error>| {
-            log::error!("Failed to fetch avatar data from {}: {}", url, e);
-            e
-        })?;
+        let response = self.fetch(&url)?;
         log::debug!("Avatar data is{}", response);
-        match serde_json::from_str::<Value>(&response) {
-            Ok(json) => {
-                self.avatar_id = json["VALUE"][0]
-                    .as_str()
-                    .and_then(|v| String::try_from(v).ok());
-                Ok(self.avatar_id.clone())
-            }
-            Err(e) => {
-                println!("Failed to parse JSON: {}", e);
-                Ok(Some(String::new()))
-            }
+        self.avatar_id = parse_avatar_id(&response);
+        self.avatar_id_fetched = Some((self.clock)());
+        Ok(self.avatar_id.clone())
+    }
+    /// Fetches the avatar id again once the cached one is older than the cache TTL.
+    /// Used to notice avatar swaps when the `/avatar/change` message was missed.
+    pub fn refresh_avatar_id(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let now = (self.clock)();
+        if self
+            .avatar_id_fetched
+            .is_none_or(|fetched| now.duration_since(fetched) >= CACHE_TTL)
+        {
+            self.avatar_id = None;
         }
+        self.get_avatar_id()
+    }
+    /// Fetches the parameter tree again, ignoring the cached copy.
+    pub fn refetch_avatar_parameters(&mut self) -> Result<Value, Box<dyn std::error::Error>> {
+        self.cached_data = None;
+        self.get_avatar_parameters()
     }
     pub fn get_avatar_parameters(&mut self) -> Result<Value, Box<dyn std::error::Error>> {
-        let now = Instant::now();
+        let now = (self.clock)();
         if let (Some(timestamp), Some(data)) = (&self.last_fetched, &self.cached_data) {
-            if now.duration_since(*timestamp) < Duration::from_secs(5) {
+            if now.duration_since(*timestamp) < CACHE_TTL {
                 log::debug!("Returning cloned avatar parameters");
                 return Ok(data.clone());
             }
         }
 
-        let url = match OscServer::get_osc_query() {
+        let url = match self.source.base_url() {
             Some(base_url) => format!("{}/avatar/parameters", base_url),
             None => return Ok(Value::Null),
         };
         self.avatar_id = None;
 
-        // --- Core logic ---
-        let response = match Handle::try_current() {
-            // Already inside runtime → spawn task instead of blocking
-            Ok(handle) => {
-                // Use `block_in_place` to temporarily allow blocking inside async
-                tokio::task::block_in_place(|| {
-                    let rt = handle.clone();
-                    rt.block_on(async { OscQueryCache::fetch_avatar_data(&url).await })
-                })
-            }
-
-            // Not inside runtime → use your global or local runtime
-            Err(_) => Tokio_RT.block_on(async { OscQueryCache::fetch_avatar_data(&url).await }),
-        }
-        .map_err(|e: Box<dyn std::error::Error>| {
-            log::error!("Failed to fetch avatar data from {}: {}", url, e);
-            e
-        })?;
+        let response = self.fetch(&url)?;
         match serde_json::from_str::<Value>(&response) {
             Ok(json) => {
                 self.last_fetched = Some(now);
@@ -127,12 +235,179 @@ impl OscQueryCache {
                 Ok(json)
             }
             Err(e) => {
-                println!("Failed to parse JSON: {}", e);
+                log::error!("Failed to parse avatar parameters JSON: {}", e);
                 Ok(Value::Null)
             }
         }
     }
 }
+/// Extracts the avatar id from an `/avatar/change` OSCQuery response.
+/// Returns `None` for malformed JSON, a missing or empty `VALUE` array, or a blank id.
+pub fn parse_avatar_id(body: &str) -> Option<String> {
+    let json = match serde_json::from_str::<Value>(body) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Failed to parse avatar id JSON: {}", e);
+            return None;
+        }
+    };
+    match json.get("VALUE").and_then(|value| value.get(0)).and_then(Value::as_str) {
+        Some(id) if !id.trim().is_empty() => Some(id.to_string()),
+        _ => {
+            log::warn!("OSCQuery avatar response has no avatar id: {}", body);
+            None
+        }
+    }
+}
 pub fn get_osc_query_cache() -> std::sync::MutexGuard<'static, OscQueryCache> {
     CACHE.lock().expect("Failed to lock OSC Query Cache")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        static START: Instant = Instant::now();
+        static ELAPSED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    }
+    /// Clock that only moves through `advance`.
+    fn fake_now() -> Instant {
+        START.with(|start| *start) + ELAPSED.with(Cell::get)
+    }
+    fn advance(by: Duration) {
+        ELAPSED.with(|elapsed| elapsed.set(elapsed.get() + by));
+    }
+
+    const TREE: &str = r#"{"CONTENTS":{"PregnancySave":{"TYPE":"f"}}}"#;
+
+    fn cache(fake: &FakeOscQuery) -> OscQueryCache {
+        OscQueryCache::with_source_and_clock(Box::new(fake.clone()), fake_now)
+    }
+
+    #[test]
+    fn avatar_id_is_the_first_value() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/change", r#"{"VALUE":["avtr_1234","ignored"]}"#);
+        let mut cache = cache(&fake);
+        assert_eq!(cache.get_avatar_id().unwrap().as_deref(), Some("avtr_1234"));
+        assert_eq!(cache.get_avatar_id().unwrap().as_deref(), Some("avtr_1234"));
+        assert_eq!(fake.fetches(), ["/avatar/change"]);
+    }
+
+    #[test]
+    fn parse_avatar_id_rejects_bad_bodies() {
+        assert_eq!(parse_avatar_id(r#"{"VALUE":["avtr_1"]}"#).as_deref(), Some("avtr_1"));
+        assert_eq!(parse_avatar_id("not json"), None);
+        assert_eq!(parse_avatar_id(r#"{"VALUE":[]}"#), None);
+        assert_eq!(parse_avatar_id(r#"{"VALUE":["  "]}"#), None);
+        assert_eq!(parse_avatar_id(r#"{"VALUE":[3]}"#), None);
+        assert_eq!(parse_avatar_id(r#"{"TYPE":"s"}"#), None);
+    }
+
+    #[test]
+    fn parameters_are_cached_for_the_ttl() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/parameters", TREE);
+        let mut cache = cache(&fake);
+        let first = cache.get_avatar_parameters().unwrap();
+        advance(CACHE_TTL - Duration::from_millis(1));
+        assert_eq!(cache.get_avatar_parameters().unwrap(), first);
+        assert_eq!(fake.fetches().len(), 1);
+        advance(Duration::from_millis(1));
+        cache.get_avatar_parameters().unwrap();
+        assert_eq!(fake.fetches().len(), 2);
+    }
+
+    #[test]
+    fn clear_avatar_waits_for_the_grace_window() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/parameters", TREE)
+            .respond("/avatar/change", r#"{"VALUE":["avtr_1"]}"#);
+        let mut cache = cache(&fake);
+        cache.clear_grace = Duration::from_millis(500);
+        cache.get_avatar_parameters().unwrap();
+        cache.get_avatar_id().unwrap();
+
+        advance(Duration::from_millis(500));
+        cache.clear_avatar();
+        assert!(cache.cached_data.is_some());
+        assert_eq!(cache.avatar_id.as_deref(), Some("avtr_1"));
+
+        advance(Duration::from_millis(1));
+        cache.clear_avatar();
+        assert!(cache.cached_data.is_none());
+        assert!(cache.avatar_id.is_none());
+        // The clear restarts the window, so an immediate second clear does nothing.
+        assert_eq!(cache.last_fetched, Some(fake_now()));
+    }
+
+    #[test]
+    fn force_clear_drops_everything_at_once() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/parameters", TREE);
+        let mut cache = cache(&fake);
+        cache.get_avatar_parameters().unwrap();
+        cache.force_clear_avatar();
+        assert!(cache.cached_data.is_none() && cache.last_fetched.is_none());
+        cache.get_avatar_parameters().unwrap();
+        assert_eq!(fake.fetches().len(), 2);
+    }
+
+    #[test]
+    fn refresh_avatar_id_refetches_after_the_ttl() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/change", r#"{"VALUE":["avtr_1"]}"#)
+            .respond("/avatar/change", r#"{"VALUE":["avtr_2"]}"#);
+        let mut cache = cache(&fake);
+        assert_eq!(cache.refresh_avatar_id().unwrap().as_deref(), Some("avtr_1"));
+        assert_eq!(cache.refresh_avatar_id().unwrap().as_deref(), Some("avtr_1"));
+        advance(CACHE_TTL);
+        assert_eq!(cache.refresh_avatar_id().unwrap().as_deref(), Some("avtr_2"));
+    }
+
+    #[test]
+    fn missing_server_gives_empty_results() {
+        struct NoServer;
+        impl OscQuerySource for NoServer {
+            fn base_url(&self) -> Option<String> {
+                None
+            }
+            fn fetch(&self, _url: &str) -> Result<String, Box<dyn std::error::Error>> {
+                unreachable!("nothing to fetch from")
+            }
+        }
+        let mut cache = OscQueryCache::with_source(Box::new(NoServer));
+        assert_eq!(cache.get_avatar_id().unwrap(), None);
+        assert_eq!(cache.get_avatar_parameters().unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn malformed_bodies_give_no_id_and_no_tree() {
+        let fake = FakeOscQuery::default();
+        fake.respond("/avatar/change", "<html>oops</html>")
+            .respond("/avatar/parameters", "{\"CONTENTS\":");
+        let mut cache = cache(&fake);
+        assert_eq!(cache.get_avatar_id().unwrap(), None);
+        assert_eq!(cache.get_avatar_parameters().unwrap(), Value::Null);
+        // A broken tree is not cached, so the next lookup asks again.
+        cache.get_avatar_parameters().unwrap();
+        assert_eq!(fake.fetches().iter().filter(|path| *path == "/avatar/parameters").count(), 2);
+    }
+
+    #[test]
+    fn a_hung_server_times_out() {
+        // Connections are accepted by the OS but never answered.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/avatar/parameters", listener.local_addr().unwrap());
+        let client = http_client(Duration::from_millis(200));
+        let started = Instant::now();
+        let result = Tokio_RT.block_on(fetch_with(&client, &url));
+        let err = result.expect_err("a server that never answers must not produce a body");
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+        let timed_out = err.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_timeout);
+        assert!(timed_out, "expected a timeout, got {}", err);
+        drop(listener);
+    }
+}
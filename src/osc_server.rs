@@ -1,15 +1,18 @@
 // src/osc_server.rs
 use std::{
-    net::{IpAddr, SocketAddrV4, UdpSocket},
+    env,
+    net::{IpAddr, SocketAddr, SocketAddrV4, UdpSocket},
     str::FromStr,
     sync::{Arc, Mutex},
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use crate::utils::env_or;
+use crate::{diagnostics, metrics};
 use lazy_static::lazy_static;
-use log::{debug, error, info};
-use rosc::{OscPacket, OscType};
+use log::{debug, error, info, warn};
+use rosc::{OscPacket, OscTime, OscType};
 use vrchat_osc::{models::OscRootNode, ServiceType, VRChatOSC};
 
 #[derive(Clone, Debug)]
@@ -17,27 +20,165 @@ pub struct VRChatOscAddresss {
     pub host: String,
     pub port: u16,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ValueType {
     Float,
     Int,
+    Long,
+    Double,
     Bool,
+    String,
     Unknown,
     // Add other types as necessary
 }
+impl From<&OscType> for ValueType {
+    fn from(value: &OscType) -> Self {
+        match value {
+            OscType::Float(_) => ValueType::Float,
+            OscType::Int(_) => ValueType::Int,
+            OscType::Long(_) => ValueType::Long,
+            OscType::Double(_) => ValueType::Double,
+            OscType::Bool(_) => ValueType::Bool,
+            OscType::String(_) => ValueType::String,
+            OscType::Blob(_)
+            | OscType::Time(_)
+            | OscType::Char(_)
+            | OscType::Color(_)
+            | OscType::Midi(_)
+            | OscType::Array(_)
+            | OscType::Nil
+            | OscType::Inf => ValueType::Unknown,
+        }
+    }
+}
+/// How far the app has got in reaching VRChat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Searching,
+    OscFound,
+    OscQueryConnected,
+    Disconnected,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionEvent {
+    OscFound,
+    OscQueryFound,
+    SendFailed,
+    /// VRChat answered: a VRChatOSC send went through or a packet arrived.
+    /// `oscquery` is set when the OSCQuery server is known.
+    Reachable { oscquery: bool },
+    /// A plain UDP send went out. These succeed with nobody listening,
+    /// so they only clear an earlier failure.
+    SendSucceeded,
+}
+fn next_connection_state(current: ConnectionState, event: ConnectionEvent) -> ConnectionState {
+    match event {
+        // Finding the plain OSC service again must not hide an established OSCQuery link.
+        ConnectionEvent::OscFound | ConnectionEvent::Reachable { oscquery: false }
+            if current == ConnectionState::OscQueryConnected =>
+        {
+            current
+        }
+        ConnectionEvent::OscFound | ConnectionEvent::Reachable { oscquery: false } => ConnectionState::OscFound,
+        ConnectionEvent::OscQueryFound | ConnectionEvent::Reachable { oscquery: true } => {
+            ConnectionState::OscQueryConnected
+        }
+        ConnectionEvent::SendFailed => ConnectionState::Disconnected,
+        ConnectionEvent::SendSucceeded if current == ConnectionState::Disconnected => ConnectionState::Searching,
+        ConnectionEvent::SendSucceeded => current,
+    }
+}
 
 lazy_static! {
     static ref OSC_PORT: Mutex<Option<u16>> = Mutex::default();
     static ref OSC_QUERY: Mutex<Option<VRChatOscAddresss>> = Mutex::default();
-    static ref VRC_PORT: Mutex<Option<u16>> = Mutex::default();
+    /// Plain OSC address of VRChat (`VRC_IP`/`VRC_PORT`), used when sending over UDP.
+    static ref VRC_ADDRESS: Mutex<Option<SocketAddr>> = Mutex::default();
     static ref UDP_SOCKET: Mutex<Option<Arc<UdpSocket>>> = Mutex::new(None);
     static ref VRC_OSC: Mutex<Option<Arc<VRChatOSC>>> = Mutex::new(None);
+    static ref UDP_FALLBACK: Mutex<bool> = Mutex::new(false);
+    static ref VRC_OVERRIDE: Mutex<Option<SocketAddr>> = Mutex::new(None);
+    static ref RECEIVE_ONLY: Mutex<bool> = Mutex::new(env_or("RECEIVE_ONLY", false));
+    /// Extra raw UDP target every send is copied to (`MIRROR_ADDRESS`).
+    static ref MIRROR_ADDRESS: Mutex<Option<SocketAddr>> =
+        Mutex::new(parse_mirror_address(&env::var("MIRROR_ADDRESS").unwrap_or_default()));
+    static ref MIRROR_SOCKET: Mutex<Option<UdpSocket>> = Mutex::new(None);
+    static ref CONNECTION_STATE: Mutex<ConnectionState> = Mutex::new(ConnectionState::Searching);
     static ref Tokio_RT: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
 }
+/// Packets "sent" while testing, instead of going out over the network.
+#[cfg(test)]
+static SENT: Mutex<Vec<OscPacket>> = Mutex::new(Vec::new());
+
+const SERVICE_NAME: &str = "Pregancy OSC";
+/// How many times OSCQuery registration is attempted before falling back to plain UDP.
+const REGISTER_ATTEMPTS: u32 = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+enum RegistrationAction {
+    Retry(Duration),
+    FallBack,
+}
+/// Decides what to do after the given failed registration attempt (counting from 1).
+/// Retries back off exponentially from 500ms.
+fn registration_action(attempt: u32, max_attempts: u32) -> RegistrationAction {
+    if attempt >= max_attempts {
+        RegistrationAction::FallBack
+    } else {
+        RegistrationAction::Retry(Duration::from_millis(500) * 2u32.pow(attempt - 1))
+    }
+}
+
+/// Parses the mirror target, e.g. `127.0.0.1:9010`. Empty means no mirroring.
+fn parse_mirror_address(value: &str) -> Option<SocketAddr> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    match value.parse() {
+        Ok(addr) => Some(addr),
+        Err(e) => {
+            warn!("Ignoring invalid MIRROR_ADDRESS {}: {}", value, e);
+            None
+        }
+    }
+}
+/// How many times in a row the receive thread is restarted before giving up.
+const MAX_RECV_RESTARTS: u32 = 5;
+/// A receive thread that ran this long counts as healthy and resets the restart count.
+const RECV_STABLE_AFTER: Duration = Duration::from_secs(60);
+/// Delay before the given restart of the receive thread (counting from 1),
+/// or `None` once the restart limit is used up. Backs off exponentially from 1s.
+fn recv_restart_delay(restart: u32) -> Option<Duration> {
+    (1..=MAX_RECV_RESTARTS)
+        .contains(&restart)
+        .then(|| Duration::from_secs(1) * 2u32.pow(restart - 1))
+}
+/// Decodes a received datagram read into a buffer of `capacity` bytes.
+/// A datagram that filled the whole buffer may have been cut short, so it is dropped
+/// with a warning instead of being decoded.
+fn decode_received(data: &[u8], capacity: usize) -> Option<OscPacket> {
+    if data.len() >= capacity {
+        warn!(
+            "Dropped an OSC packet that filled the {} byte receive buffer and may be truncated; raise RECV_BUFFER_SIZE to accept larger bundles",
+            capacity
+        );
+        return None;
+    }
+    match rosc::decoder::decode_udp(data) {
+        Ok((_, packet)) => Some(packet),
+        Err(e) => {
+            debug!("Failed to decode OSC packet: {}", e);
+            None
+        }
+    }
+}
 
 pub trait PacketHandler: Send + Sync {
     fn handle(&self, packet: OscPacket);
     fn start(&self) {}
+    /// Called from the discovery callback when VRChat (re)appears. Must not block.
+    fn on_connect(&self) {}
 }
 
 pub struct OscServer;
@@ -46,6 +187,7 @@ impl OscServer {
     pub fn start(host: &str, port: u16, handlers: Vec<Arc<dyn PacketHandler>>) {
         let addr = SocketAddrV4::from_str(&format!("{}:{}", host, port)).unwrap();
         let socket = UdpSocket::bind(addr).expect("Could not bind socket");
+        Self::set_osc_port(socket.local_addr().unwrap().port());
         let socket = Arc::new(socket);
         {
             let mut socket_guard = UDP_SOCKET.lock().unwrap();
@@ -54,7 +196,6 @@ impl OscServer {
 
         std::thread::spawn(move || {
             let sock = UDP_SOCKET.lock().unwrap().as_ref().unwrap().clone();
-            Self::set_osc_port(sock.local_addr().unwrap().port());
             info!(
                 "Listening for OSC packets on {}",
                 sock.local_addr().unwrap()
@@ -65,57 +206,166 @@ impl OscServer {
             for handler in &handlers {
                 handler.start();
             }
-            let mut buf = [0u8; rosc::decoder::MTU];
-            loop {
-                match sock.recv_from(&mut buf) {
-                    Ok((size, _)) => {
-                        if let Ok((_, packet)) = rosc::decoder::decode_udp(&buf[..size]) {
-                            for handler in &handlers {
-                                handler.handle(packet.clone());
-                            }
+            Self::supervise_recv(sock, handlers);
+        });
+    }
+    /// Runs the receive loop on its own thread and restarts it when it stops or panics.
+    fn supervise_recv(sock: Arc<UdpSocket>, handlers: Vec<Arc<dyn PacketHandler>>) {
+        let mut restarts = 0;
+        loop {
+            let started = Instant::now();
+            let (recv_sock, recv_handlers) = (sock.clone(), handlers.clone());
+            let result = std::thread::spawn(move || Self::recv_loop(&recv_sock, &recv_handlers)).join();
+            if started.elapsed() >= RECV_STABLE_AFTER {
+                restarts = 0;
+            }
+            restarts += 1;
+            let reason = if result.is_err() { "panicked" } else { "stopped" };
+            match recv_restart_delay(restarts) {
+                Some(delay) => {
+                    warn!(
+                        "OSC receive thread {}, restarting in {:?} (restart {} of {})",
+                        reason, delay, restarts, MAX_RECV_RESTARTS
+                    );
+                    sleep(delay);
+                }
+                None => {
+                    error!(
+                        "OSC receive thread {} again, giving up after {} restarts",
+                        reason, MAX_RECV_RESTARTS
+                    );
+                    return;
+                }
+            }
+        }
+    }
+    /// Receives and dispatches packets until the socket reports an error.
+    fn recv_loop(sock: &UdpSocket, handlers: &[Arc<dyn PacketHandler>]) {
+        let mut buf = vec![0u8; env_or("RECV_BUFFER_SIZE", rosc::decoder::MTU).max(1)];
+        loop {
+            match sock.recv_from(&mut buf) {
+                Ok((size, _)) => {
+                    metrics::record_packet_received();
+                    Self::record_connection_event(ConnectionEvent::Reachable { oscquery: false });
+                    if let Some(packet) = decode_received(&buf[..size], buf.len()) {
+                        diagnostics::record_osc("in", &packet);
+                        for handler in handlers {
+                            handler.handle(packet.clone());
                         }
                     }
-                    Err(e) => {
-                        error!("Error receiving from socket: {}", e);
-                        break;
-                    }
+                }
+                Err(e) => {
+                    error!("Error receiving from socket: {}", e);
+                    return;
                 }
             }
-        });
+        }
+    }
+    /// Starts the plain UDP server using `PORT`, `VRC_IP` and `VRC_PORT` from the environment.
+    pub fn start_udp_from_env(handlers: Vec<Arc<dyn PacketHandler>>) {
+        let portnumber: u16 = env::var("PORT")
+            .unwrap_or("0".to_string())
+            .parse()
+            .expect("PORT must be a valid u16");
+        Self::start("0.0.0.0", portnumber, handlers);
+        info!(
+            "OSC Server started on port {}",
+            Self::get_osc_port().unwrap()
+        );
+        let vrc_osc: IpAddr = env::var("VRC_IP")
+            .unwrap_or("127.0.0.1".to_string())
+            .parse()
+            .expect("address must be a valid IP address");
+        let vrc_port: u16 = env::var("VRC_PORT")
+            .unwrap_or("9000".to_string())
+            .parse()
+            .expect("vrc_port must be a valid u16");
+        Self::set_vrc_address(vrc_osc, vrc_port);
+    }
+    /// Switches to the plain UDP server after OSCQuery could not be set up.
+    fn fall_back_to_udp(handlers: Vec<Arc<dyn PacketHandler>>) {
+        warn!("OSCQuery unavailable, falling back to plain UDP OSC");
+        VRC_OSC.lock().unwrap().take();
+        *UDP_FALLBACK.lock().unwrap() = true;
+        Self::start_udp_from_env(handlers);
+    }
+    /// Whether OSCQuery setup failed and the plain UDP server is used instead.
+    pub fn is_udp_fallback() -> bool {
+        *UDP_FALLBACK.lock().unwrap()
     }
     pub async fn packet_handler(handlers: Vec<Arc<dyn PacketHandler>>) {
-        let vrchat_osc = VRChatOSC::new().await.expect("Failed to create VRChatOSC");
+        let vrchat_osc = match VRChatOSC::new().await {
+            Ok(vrchat_osc) => vrchat_osc,
+            Err(e) => {
+                error!("Failed to create VRChatOSC: {}", e);
+                Self::fall_back_to_udp(handlers);
+                return;
+            }
+        };
         {
             let mut vrc_osc_guard = VRC_OSC.lock().unwrap();
             *vrc_osc_guard = Some(vrchat_osc);
         }
         let vrchat_osc = VRC_OSC.lock().unwrap().as_ref().unwrap().clone();
+        let connect_handlers = handlers.clone();
         vrchat_osc
-            .on_connect(move |res| match res {
-                ServiceType::Osc(name, addr) => {
-                    info!("Found vrchat OSC server: {} at {}", name, addr);
+            .on_connect(move |res| {
+                match res {
+                    ServiceType::Osc(name, addr) => {
+                        info!("Found vrchat OSC server: {} at {}", name, addr);
+                        Self::record_connection_event(ConnectionEvent::OscFound);
+                    }
+                    ServiceType::OscQuery(name, addr) => {
+                        info!("Connected to OSCQuery server: {} at {}", name, addr);
+                        Self::record_connection_event(ConnectionEvent::OscQueryFound);
+                        OSC_QUERY.lock().unwrap().replace(VRChatOscAddresss {
+                            host: addr.ip().to_string(),
+                            port: addr.port(),
+                        });
+                    }
                 }
-                ServiceType::OscQuery(name, addr) => {
-                    info!("Connected to OSCQuery server: {} at {}", name, addr);
-                    OSC_QUERY.lock().unwrap().replace(VRChatOscAddresss {
-                        host: addr.ip().to_string(),
-                        port: addr.port(),
-                    });
+                for handler in &connect_handlers {
+                    handler.on_connect();
                 }
             })
             .await;
+        let mut attempt = 0;
+        loop {
+            let receivers = handlers.clone();
+            let root_node = OscRootNode::new().with_avatar();
+            let result = vrchat_osc
+                .register(SERVICE_NAME, root_node, move |packet| {
+                    metrics::record_packet_received();
+                    Self::record_connection_event(ConnectionEvent::Reachable {
+                        oscquery: Self::get_osc_query().is_some(),
+                    });
+                    diagnostics::record_osc("in", &packet);
+                    for handler in &receivers {
+                        handler.handle(packet.clone());
+                    }
+                })
+                .await;
+            let Err(e) = result else {
+                break;
+            };
+            attempt += 1;
+            error!("Failed to register packet handler (attempt {}): {}", attempt, e);
+            match registration_action(attempt, REGISTER_ATTEMPTS) {
+                RegistrationAction::Retry(delay) => {
+                    // Drop anything the failed attempt left behind before trying again.
+                    let _ = vrchat_osc.unregister(SERVICE_NAME).await;
+                    tokio::time::sleep(delay).await;
+                }
+                RegistrationAction::FallBack => {
+                    let _ = vrchat_osc.unregister(SERVICE_NAME).await;
+                    Self::fall_back_to_udp(handlers);
+                    return;
+                }
+            }
+        }
         for handler in &handlers {
             handler.start();
         }
-        let root_node = OscRootNode::new().with_avatar();
-        vrchat_osc
-            .register("Pregancy OSC", root_node, move |packet| {
-                for handler in &handlers {
-                    handler.handle(packet.clone());
-                }
-            })
-            .await
-            .expect("Failed to register packet handler");
     }
     pub fn get_osc_port() -> Option<u16> {
         let port_guard = OSC_PORT.lock().unwrap();
@@ -125,12 +375,44 @@ impl OscServer {
         let mut port_guard = OSC_PORT.lock().unwrap();
         *port_guard = Some(port);
     }
+    /// Whether outgoing sends are disabled so the app only observes the avatar.
+    pub fn is_receive_only() -> bool {
+        *RECEIVE_ONLY.lock().unwrap()
+    }
+    pub fn set_receive_only(value: bool) {
+        *RECEIVE_ONLY.lock().unwrap() = value;
+    }
+    pub fn connection_state() -> ConnectionState {
+        *CONNECTION_STATE.lock().unwrap()
+    }
+    fn record_connection_event(event: ConnectionEvent) {
+        let mut state = CONNECTION_STATE.lock().unwrap();
+        *state = next_connection_state(*state, event);
+    }
+    /// Sets where plain UDP sends go. This is VRChat's OSC port, not an OSCQuery server,
+    /// so OSCQuery lookups stay unavailable.
+    /// VRChat only counts as found once a packet arrives from it.
     pub fn set_vrc_address(host: IpAddr, port: u16) {
-        let mut osc_query_guard = OSC_QUERY.lock().unwrap();
-        *osc_query_guard = Some(VRChatOscAddresss {
-            host: host.to_string(),
-            port,
-        });
+        *VRC_ADDRESS.lock().unwrap() = Some(SocketAddr::new(host, port));
+    }
+
+    /// Sends to `addr` instead of the discovered VRChat client. `None` restores discovery.
+    pub fn set_vrc_override(addr: Option<SocketAddr>) {
+        match addr {
+            Some(addr) => info!("Sending OSC to manual address {}", addr),
+            None => info!("Manual OSC address cleared, using discovery"),
+        }
+        *VRC_OVERRIDE.lock().unwrap() = addr;
+    }
+    pub fn get_vrc_override() -> Option<SocketAddr> {
+        *VRC_OVERRIDE.lock().unwrap()
+    }
+    /// Address the plain UDP path sends to: the manual override, else the configured
+    /// address, else VRChat's default port on localhost.
+    pub fn get_vrc_address() -> String {
+        Self::get_vrc_override()
+            .or(*VRC_ADDRESS.lock().unwrap())
+            .map_or_else(|| "127.0.0.1:9000".to_string(), |addr| addr.to_string())
     }
 
     pub fn get_osc_query() -> Option<String> {
@@ -142,25 +424,55 @@ impl OscServer {
     }
 
     pub fn send_osc_data(addr: String, args: Vec<OscType>) {
+        Self::send_packet(OscPacket::Message(rosc::OscMessage { addr, args }));
+    }
+
+    /// Sends several messages as one `OscPacket::Bundle` so the receiver applies them together.
+    pub fn send_osc_bundle(messages: Vec<rosc::OscMessage>) {
+        Self::send_packet(OscPacket::Bundle(rosc::OscBundle {
+            // (0, 1) is the OSC "immediately" time tag.
+            timetag: OscTime::from((0, 1)),
+            content: messages.into_iter().map(OscPacket::Message).collect(),
+        }));
+    }
+
+    fn send_packet(packet: OscPacket) {
+        if Self::is_receive_only() {
+            debug!("Receive-only mode, not sending {:?}", packet);
+            return;
+        }
+        metrics::record_packet_sent();
+        diagnostics::record_osc("out", &packet);
+        let mirror = *MIRROR_ADDRESS.lock().unwrap();
+        if let Some(mirror) = mirror {
+            Self::send_mirror(&packet, mirror);
+        }
+        #[cfg(test)]
+        if Self::capture_sent(&packet) {
+            return;
+        }
         let vrc_osc_guard = VRC_OSC.lock().unwrap();
         if let Some(vrc_osc) = vrc_osc_guard.as_ref() {
             debug!("Calling Tokio spawn");
             let vrc_osc = Arc::clone(vrc_osc);
+            let target_override = Self::get_vrc_override();
             // Spawn a task on the existing Tokio runtime
             Tokio_RT.spawn(async move {
                 debug!("Sending OSC data to VRChat via VRChatOSC");
-                vrc_osc
-                    .send(
-                        OscPacket::Message(rosc::OscMessage {
-                            addr: addr,
-                            args: args,
-                        }),
-                        "VRChat-Client-*",
-                    )
-                    .await
-                    .expect("Failed to send OSC data");
+                let result = match target_override {
+                    Some(addr) => vrc_osc.send_to_addr(packet, addr).await,
+                    None => vrc_osc.send(packet, "VRChat-Client-*").await,
+                };
+                match result {
+                    Ok(()) => Self::record_connection_event(ConnectionEvent::Reachable {
+                        oscquery: Self::get_osc_query().is_some(),
+                    }),
+                    Err(e) => {
+                        error!("Failed to send OSC data: {}", e);
+                        Self::record_connection_event(ConnectionEvent::SendFailed);
+                    }
+                }
             });
-            return;
         } else {
             let sock = {
                 let socket_guard = UDP_SOCKET.lock().unwrap();
@@ -170,22 +482,54 @@ impl OscServer {
                     .try_clone()
                     .unwrap()
             };
-            let target_address = OSC_QUERY
-                .lock()
-                .unwrap()
-                .as_ref()
-                .map(|addr| format!("{}:{}", addr.host, addr.port))
-                .unwrap_or_else(|| "127.0.0.1:9000".to_string());
+            let target_address = Self::get_vrc_address();
 
-            sock.send_to(
-                &rosc::encoder::encode(&OscPacket::Message(rosc::OscMessage {
-                    addr: addr,
-                    args: args,
-                }))
-                .unwrap(),
-                target_address,
-            )
-            .expect("Failed to send OSC data");
+            match sock.send_to(&rosc::encoder::encode(&packet).unwrap(), target_address) {
+                Ok(_) => Self::record_connection_event(ConnectionEvent::SendSucceeded),
+                Err(e) => {
+                    error!("Failed to send OSC data: {}", e);
+                    Self::record_connection_event(ConnectionEvent::SendFailed);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn capture_sent(packet: &OscPacket) -> bool {
+        SENT.lock().unwrap().push(packet.clone());
+        true
+    }
+    /// Drains the packets sent since the last call.
+    #[cfg(test)]
+    pub fn take_sent() -> Vec<OscPacket> {
+        std::mem::take(&mut *SENT.lock().unwrap())
+    }
+    #[cfg(test)]
+    pub fn set_connection_state(state: ConnectionState) {
+        *CONNECTION_STATE.lock().unwrap() = state;
+    }
+    #[cfg(test)]
+    fn set_mirror_address(mirror: Option<SocketAddr>) {
+        *MIRROR_ADDRESS.lock().unwrap() = mirror;
+    }
+    /// Copies a packet to the raw UDP mirror target. Failures are only logged so they never
+    /// affect the main send.
+    fn send_mirror(packet: &OscPacket, mirror: SocketAddr) {
+        let mut socket_guard = MIRROR_SOCKET.lock().unwrap();
+        if socket_guard.is_none() {
+            match UdpSocket::bind(("0.0.0.0", 0)) {
+                Ok(socket) => *socket_guard = Some(socket),
+                Err(e) => {
+                    error!("Failed to open mirror socket: {}", e);
+                    return;
+                }
+            }
+        }
+        let result = rosc::encoder::encode(packet)
+            .map_err(|e| std::io::Error::other(e.to_string()))
+            .and_then(|bytes| socket_guard.as_ref().unwrap().send_to(&bytes, mirror));
+        if let Err(e) = result {
+            debug!("Failed to mirror OSC data to {}: {}", mirror, e);
         }
     }
 
@@ -217,3 +561,247 @@ impl OscServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::lock_globals;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn registration_retries_with_backoff_then_falls_back() {
+        let actions: Vec<RegistrationAction> =
+            (1..=REGISTER_ATTEMPTS).map(|attempt| registration_action(attempt, REGISTER_ATTEMPTS)).collect();
+        assert_eq!(
+            actions,
+            [
+                RegistrationAction::Retry(Duration::from_millis(500)),
+                RegistrationAction::Retry(Duration::from_millis(1000)),
+                RegistrationAction::Retry(Duration::from_millis(2000)),
+                RegistrationAction::FallBack,
+            ]
+        );
+        assert_eq!(registration_action(1, 1), RegistrationAction::FallBack);
+    }
+
+    #[test]
+    fn udp_address_is_not_taken_for_an_oscquery_server() {
+        let _guard = lock_globals();
+        OscServer::set_vrc_override(None);
+        OscServer::set_vrc_address("127.0.0.1".parse().unwrap(), 9123);
+        assert_eq!(OscServer::get_vrc_address(), "127.0.0.1:9123");
+        assert_eq!(OscServer::get_osc_query(), None);
+    }
+
+    #[test]
+    fn connection_state_transitions() {
+        use ConnectionEvent as E;
+        use ConnectionState as S;
+        let cases = [
+            (S::Searching, E::OscFound, S::OscFound),
+            (S::OscQueryConnected, E::OscFound, S::OscQueryConnected),
+            (S::Searching, E::OscQueryFound, S::OscQueryConnected),
+            (S::OscQueryConnected, E::SendFailed, S::Disconnected),
+            (S::Disconnected, E::Reachable { oscquery: true }, S::OscQueryConnected),
+            (S::Disconnected, E::Reachable { oscquery: false }, S::OscFound),
+            (S::OscQueryConnected, E::Reachable { oscquery: false }, S::OscQueryConnected),
+            (S::Disconnected, E::SendSucceeded, S::Searching),
+            (S::Searching, E::SendSucceeded, S::Searching),
+            (S::OscFound, E::SendSucceeded, S::OscFound),
+        ];
+        for (current, event, expected) in cases {
+            assert_eq!(next_connection_state(current, event), expected, "{:?} + {:?}", current, event);
+        }
+    }
+
+    #[test]
+    fn configuring_an_address_does_not_count_as_found() {
+        let _guard = lock_globals();
+        *CONNECTION_STATE.lock().unwrap() = ConnectionState::Searching;
+        OscServer::set_vrc_address("127.0.0.1".parse().unwrap(), 9000);
+        assert_eq!(OscServer::connection_state(), ConnectionState::Searching);
+        OscServer::record_connection_event(ConnectionEvent::SendFailed);
+        OscServer::record_connection_event(ConnectionEvent::SendSucceeded);
+        assert_eq!(OscServer::connection_state(), ConnectionState::Searching);
+        OscServer::record_connection_event(ConnectionEvent::Reachable { oscquery: false });
+        assert_eq!(OscServer::connection_state(), ConnectionState::OscFound);
+    }
+
+    #[test]
+    fn manual_address_overrides_until_cleared() {
+        let _guard = lock_globals();
+        OscServer::set_vrc_address("127.0.0.1".parse().unwrap(), 9000);
+        OscServer::set_vrc_override(Some("192.168.1.20:9100".parse().unwrap()));
+        assert_eq!(OscServer::get_vrc_address(), "192.168.1.20:9100");
+        assert_eq!(OscServer::get_vrc_override(), Some("192.168.1.20:9100".parse().unwrap()));
+        OscServer::set_vrc_override(None);
+        assert_eq!(OscServer::get_vrc_address(), "127.0.0.1:9000");
+    }
+
+    #[test]
+    fn receive_only_mode_sends_nothing() {
+        let _guard = lock_globals();
+        OscServer::take_sent();
+        OscServer::set_receive_only(true);
+        OscServer::send_osc_data("/avatar/parameters/ChildCount".to_string(), vec![OscType::Int(1)]);
+        OscServer::send_osc_bundle(vec![rosc::OscMessage {
+            addr: "/avatar/parameters/PregnancySave".to_string(),
+            args: vec![OscType::Float(0.5)],
+        }]);
+        assert!(OscServer::take_sent().is_empty());
+
+        OscServer::set_receive_only(false);
+        OscServer::send_osc_data("/avatar/parameters/ChildCount".to_string(), vec![OscType::Int(1)]);
+        assert_eq!(OscServer::take_sent().len(), 1);
+    }
+
+    fn encoded(addr: &str, args: Vec<OscType>) -> Vec<u8> {
+        rosc::encoder::encode(&OscPacket::Message(rosc::OscMessage { addr: addr.to_string(), args })).unwrap()
+    }
+
+    #[test]
+    fn datagrams_smaller_than_the_buffer_decode() {
+        let data = encoded("/avatar/parameters/ChildCount", vec![OscType::Int(2)]);
+        match decode_received(&data, data.len() + 1) {
+            Some(OscPacket::Message(msg)) => assert_eq!(msg.args, [OscType::Int(2)]),
+            other => panic!("expected a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn datagrams_that_fill_the_buffer_are_dropped() {
+        let data = encoded("/avatar/parameters/ChildCount", vec![OscType::Int(2)]);
+        // An exact fit cannot be told apart from a truncated read.
+        assert!(decode_received(&data, data.len()).is_none());
+        assert!(decode_received(&data[..16], 16).is_none());
+    }
+
+    #[test]
+    fn oversized_packets_are_not_misdecoded() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let data = encoded("/avatar/parameters/PregnancySave", vec![OscType::String("x".repeat(64))]);
+        sender.send_to(&data, receiver.local_addr().unwrap()).unwrap();
+
+        let mut buf = [0u8; 32];
+        let (size, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(size, buf.len());
+        assert!(decode_received(&buf[..size], buf.len()).is_none());
+    }
+
+    #[test]
+    fn recv_restarts_back_off_then_give_up() {
+        let delays: Vec<Option<Duration>> = (0..=MAX_RECV_RESTARTS + 1).map(recv_restart_delay).collect();
+        assert_eq!(
+            delays,
+            [
+                None,
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(4)),
+                Some(Duration::from_secs(8)),
+                Some(Duration::from_secs(16)),
+                None,
+            ]
+        );
+    }
+
+    /// Panics on the first packet it sees, then counts the rest.
+    struct PanicOnce(Arc<AtomicUsize>);
+    impl PacketHandler for PanicOnce {
+        fn handle(&self, _packet: OscPacket) {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("handler failed");
+            }
+        }
+    }
+
+    #[test]
+    fn a_dead_recv_thread_is_respawned() {
+        let _guard = lock_globals();
+        let sock = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let addr = sock.local_addr().unwrap();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let handlers: Vec<Arc<dyn PacketHandler>> = vec![Arc::new(PanicOnce(seen.clone()))];
+        std::thread::spawn(move || OscServer::supervise_recv(sock, handlers));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let data = rosc::encoder::encode(&OscPacket::Message(rosc::OscMessage {
+            addr: "/avatar/parameters/ChildCount".to_string(),
+            args: vec![OscType::Int(1)],
+        }))
+        .unwrap();
+        sender.send_to(&data, addr).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while seen.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        // Queued while the thread is down; only a respawned thread can pick it up.
+        sender.send_to(&data, addr).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while seen.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn every_osc_type_is_classified() {
+        use rosc::{OscArray, OscColor, OscMidiMessage};
+        let cases = [
+            (OscType::Float(1.0), ValueType::Float),
+            (OscType::Int(1), ValueType::Int),
+            (OscType::Long(1), ValueType::Long),
+            (OscType::Double(1.0), ValueType::Double),
+            (OscType::Bool(true), ValueType::Bool),
+            (OscType::String("1".to_string()), ValueType::String),
+            (OscType::Blob(vec![1]), ValueType::Unknown),
+            (OscType::Time(OscTime { seconds: 1, fractional: 0 }), ValueType::Unknown),
+            (OscType::Char('1'), ValueType::Unknown),
+            (OscType::Color(OscColor { red: 1, green: 1, blue: 1, alpha: 1 }), ValueType::Unknown),
+            (OscType::Midi(OscMidiMessage { port: 1, status: 1, data1: 1, data2: 1 }), ValueType::Unknown),
+            (OscType::Array(OscArray { content: vec![OscType::Int(1)] }), ValueType::Unknown),
+            (OscType::Nil, ValueType::Unknown),
+            (OscType::Inf, ValueType::Unknown),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(ValueType::from(&value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn mirror_addresses_are_parsed() {
+        assert_eq!(parse_mirror_address(""), None);
+        assert_eq!(parse_mirror_address("  "), None);
+        assert_eq!(parse_mirror_address(" 127.0.0.1:9010 "), Some("127.0.0.1:9010".parse().unwrap()));
+        assert_eq!(parse_mirror_address("127.0.0.1"), None);
+        assert_eq!(parse_mirror_address("localhost:9010"), None);
+    }
+
+    /// The mirror receives every send. Under test the VRChatOSC branch of `send_packet`
+    /// is replaced by the `capture_sent` capture, so `take_sent` stands in for VRChat.
+    #[test]
+    fn every_send_reaches_the_mirror_and_the_capture() {
+        let _guard = lock_globals();
+        let receive_only = OscServer::is_receive_only();
+        OscServer::set_receive_only(false);
+        let mirror = UdpSocket::bind("127.0.0.1:0").unwrap();
+        mirror.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let previous = *MIRROR_ADDRESS.lock().unwrap();
+        OscServer::set_mirror_address(Some(mirror.local_addr().unwrap()));
+        OscServer::take_sent();
+
+        OscServer::send_osc_data("/avatar/parameters/ChildCount".to_string(), vec![OscType::Int(2)]);
+        OscServer::set_mirror_address(previous);
+        OscServer::set_receive_only(receive_only);
+
+        let expected = OscPacket::Message(rosc::OscMessage {
+            addr: "/avatar/parameters/ChildCount".to_string(),
+            args: vec![OscType::Int(2)],
+        });
+        let mut buf = [0u8; 1024];
+        let (size, _) = mirror.recv_from(&mut buf).unwrap();
+        assert_eq!(rosc::decoder::decode_udp(&buf[..size]).unwrap().1, expected);
+        assert_eq!(OscServer::take_sent(), [expected]);
+    }
+}
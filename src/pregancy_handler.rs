@@ -1,25 +1,39 @@
+#[cfg(feature = "discord")]
+use crate::discord_presence;
+#[cfg(feature = "local_api")]
+use crate::local_api;
+#[cfg(feature = "notifications")]
+use crate::notifications;
+use crate::settings::{self, Settings};
+use crate::{diagnostics, metrics};
 use crate::osc_query_cache::get_osc_query_cache;
-use crate::osc_server::{OscServer, PacketHandler, ValueType};
-use crate::utils::{get_save_path, json_path_exists};
-use chrono::{DateTime, Duration, Local};
+use crate::osc_server::{ConnectionState, OscServer, PacketHandler, ValueType};
+use crate::utils::{env_or, get_save_path, json_path_exists, set_save_path_override};
+use chrono::{DateTime, Duration, Local, TimeZone};
 use lazy_static::lazy_static;
 use log::info;
 use rosc::{OscPacket, OscType};
 use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
 use strum::IntoEnumIterator;
 
 use eframe::egui::{self, Key};
 use eframe::App as EguiApp;
 // ChildCount, PregnancySave, GestationTime, Gestation (1-4)
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, PartialEq)]
 struct ChildInfo {
     conception_time: Option<DateTime<Local>>,
     gestation_time: f32,
     gestation: GestationType,
     number_of_childern: u8,
+    /// Set while `conception_time` is a planned start in the future.
+    scheduled: bool,
+    /// Conception times of the second child onwards when children were conceived separately
+    /// (`CONCURRENT_PREGNANCIES`). Children without an entry share `conception_time`.
+    additional_conceptions: Vec<DateTime<Local>>,
 }
 impl Default for ChildInfo {
     fn default() -> Self {
@@ -28,6 +42,8 @@ impl Default for ChildInfo {
             gestation_time: 8f32,
             gestation: GestationType::Hours,
             number_of_childern: 0,
+            scheduled: false,
+            additional_conceptions: Vec::new(),
         }
     }
 }
@@ -37,7 +53,7 @@ impl Serialize for ChildInfo {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("ChildInfo", 4)?;
+        let mut state = serializer.serialize_struct("ChildInfo", 6)?;
         if let Some(_dt) = self.conception_time {
             state.serialize_field(
                 "conception_time",
@@ -50,6 +66,8 @@ impl Serialize for ChildInfo {
         state.serialize_field("gestation_time", &self.gestation_time)?;
         state.serialize_field("gestation", &self.gestation)?;
         state.serialize_field("number_of_childern", &self.number_of_childern)?;
+        state.serialize_field("scheduled", &self.scheduled)?;
+        state.serialize_field("additional_conceptions", &self.additional_conceptions)?;
         state.end()
     }
 }
@@ -65,6 +83,10 @@ impl<'de> Deserialize<'de> for ChildInfo {
             gestation_time: f32,
             gestation: GestationType,
             number_of_childern: u8,
+            #[serde(default)]
+            scheduled: bool,
+            #[serde(default)]
+            additional_conceptions: Vec<DateTime<Local>>,
         }
 
         let helper = ChildInfoHelper::deserialize(deserializer)?;
@@ -81,12 +103,147 @@ impl<'de> Deserialize<'de> for ChildInfo {
             gestation_time: helper.gestation_time,
             gestation: helper.gestation,
             number_of_childern: helper.number_of_childern,
+            scheduled: helper.scheduled,
+            additional_conceptions: helper.additional_conceptions,
         })
     }
 }
 #[derive(Serialize, Deserialize, Debug, Default)]
 struct SaveData {
     avatar_ids: HashMap<String, ChildInfo>,
+    /// Gestation settings given to avatars seen for the first time.
+    #[serde(default)]
+    default_gestation: GestationDefaults,
+    /// Per-avatar defaults that take priority over `default_gestation`.
+    #[serde(default)]
+    avatar_defaults: HashMap<String, GestationDefaults>,
+    /// Per-avatar parameter addresses, keyed by avatar id then parameter name,
+    /// e.g. `"PregnancySave": "/avatar/parameters/Womb/Progress"`.
+    /// Parameters without an entry use `/avatar/parameters/<name>`.
+    #[serde(default)]
+    address_overrides: HashMap<String, HashMap<String, String>>,
+    /// Completed pregnancies, oldest first.
+    #[serde(default)]
+    history: Vec<PregnancyRecord>,
+    /// Avatars whose fertility system is left to another controller.
+    #[serde(default)]
+    unmanaged_avatars: HashSet<String>,
+}
+impl SaveData {
+    fn is_managed(&self, avatar_id: &str) -> bool {
+        !self.unmanaged_avatars.contains(avatar_id)
+    }
+}
+/// A pregnancy that ended in a birth, whether given early or after its due date.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PregnancyRecord {
+    avatar_id: String,
+    conception_time: DateTime<Local>,
+    birth_time: DateTime<Local>,
+    number_of_childern: u8,
+}
+/// Totals shown in the stats panel.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Stats {
+    pregnancies: usize,
+    children: u32,
+    average_gestation: Option<Duration>,
+    last_birth: Option<DateTime<Local>>,
+}
+fn compute_stats(history: &[PregnancyRecord]) -> Stats {
+    let total_gestation_secs: i64 = history
+        .iter()
+        .map(|record| (record.birth_time - record.conception_time).num_seconds())
+        .sum();
+    Stats {
+        pregnancies: history.len(),
+        children: history.iter().map(|record| record.number_of_childern as u32).sum(),
+        average_gestation: (!history.is_empty())
+            .then(|| Duration::seconds(total_gestation_secs / history.len() as i64)),
+        last_birth: history.iter().map(|record| record.birth_time).max(),
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct GestationDefaults {
+    gestation_time: f32,
+    gestation: GestationType,
+}
+impl Default for GestationDefaults {
+    fn default() -> Self {
+        GestationDefaults {
+            gestation_time: 8f32,
+            gestation: GestationType::Hours,
+        }
+    }
+}
+/// Picks the child data an avatar starts with when it is loaded, and whether it was reset.
+/// With `auto_reset` a saved pregnancy is dropped, keeping the avatar's gestation settings.
+fn child_on_load(saved: Option<ChildInfo>, fresh: ChildInfo, auto_reset: bool) -> (ChildInfo, bool) {
+    match saved {
+        Some(saved) if auto_reset => (
+            ChildInfo {
+                conception_time: None,
+                number_of_childern: 0,
+                scheduled: false,
+                additional_conceptions: Vec::new(),
+                ..saved
+            },
+            true,
+        ),
+        Some(saved) => (saved, false),
+        None => (fresh, false),
+    }
+}
+/// A conception time that cannot be right, usually because the system clock was wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClockDrift {
+    /// Conceived in the future without being scheduled.
+    FutureDated,
+    /// Conceived far longer ago than any real pregnancy could run.
+    Ancient,
+}
+/// Slack for small clock corrections before a conception counts as future-dated.
+const FUTURE_TOLERANCE_SECS: i64 = 60;
+/// Checks the stored conception time against `now`. A pregnancy counts as ancient once its
+/// elapsed time is over 100 gestations and over a year.
+fn conception_drift(child: &ChildInfo, now: DateTime<Local>) -> Option<ClockDrift> {
+    let conception = child.conception_time?;
+    let elapsed_secs = (now - conception).num_seconds();
+    if elapsed_secs < -FUTURE_TOLERANCE_SECS && !child.scheduled {
+        Some(ClockDrift::FutureDated)
+    } else if elapsed_secs as f64 > (gestation_duration_secs(child) * 100.0).max(365.0 * 86_400.0) {
+        Some(ClockDrift::Ancient)
+    } else {
+        None
+    }
+}
+/// Smallest gestation time the UI accepts, in the selected unit.
+const MIN_GESTATION_TIME: f32 = 0.01;
+/// Converts a gestation time between units, keeping the total duration.
+/// Returns `None` when the result would fall below `MIN_GESTATION_TIME` or drift from the
+/// original duration by more than a second (or a millionth for very long ones), since the
+/// UI would then change the due date.
+fn convert_gestation_time(time: f32, from: GestationType, to: GestationType) -> Option<f32> {
+    let total_secs = time as f64 * from.seconds_per_unit() as f64;
+    let converted = (total_secs / to.seconds_per_unit() as f64) as f32;
+    let drift_secs = (converted as f64 * to.seconds_per_unit() as f64 - total_secs).abs();
+    (converted >= MIN_GESTATION_TIME && drift_secs <= (total_secs * 1e-6).max(1.0)).then_some(converted)
+}
+/// Builds the starting `ChildInfo` for an avatar that has no saved entry yet.
+fn new_child_info(data: &SaveData, avatar_id: &str) -> ChildInfo {
+    let defaults = data
+        .avatar_defaults
+        .get(avatar_id)
+        .copied()
+        .unwrap_or(data.default_gestation);
+    ChildInfo {
+        conception_time: None,
+        gestation_time: defaults.gestation_time,
+        gestation: defaults.gestation,
+        number_of_childern: 0,
+        scheduled: false,
+        additional_conceptions: Vec::new(),
+    }
 }
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, strum_macros::EnumIter)]
@@ -99,26 +256,62 @@ enum GestationType {
 }
 impl GestationType {
     /// Returns the number of seconds that one unit represents.
-    /// For Months, we’re using an approximation (30 days per month).
+    /// Months use the configured calendar (30 days per month by default).
     #[inline] // Suggests to inline this small function
     pub fn seconds_per_unit(self) -> i64 {
+        self.seconds_per_unit_in(*ActiveCalendar)
+    }
+    pub fn seconds_per_unit_in(self, calendar: Calendar) -> i64 {
         match self {
             GestationType::Hours => 3600,
             GestationType::Days => 86400,
             GestationType::Weeks => 604800,
-            GestationType::Months => 2592000,
+            GestationType::Months => calendar.month_secs,
             GestationType::Mins => 60,
         }
     }
 }
-impl ToString for GestationType {
-    fn to_string(&self) -> String {
+/// Lengths of the calendar units that vary, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calendar {
+    pub month_secs: i64,
+    pub year_secs: i64,
+}
+impl Calendar {
+    /// 30-day months and 365-day years.
+    pub const SIMPLE: Calendar = Calendar::from_days(30.0, 365.0);
+    /// Average Gregorian months (30.44 days) and years (365.25 days).
+    pub const REALISTIC: Calendar = Calendar::from_days(30.44, 365.25);
+    pub const fn from_days(month_days: f64, year_days: f64) -> Calendar {
+        Calendar {
+            month_secs: (month_days * 86_400.0) as i64,
+            year_secs: (year_days * 86_400.0) as i64,
+        }
+    }
+    /// `CALENDAR=realistic` picks the realistic calendar, otherwise `MONTH_DAYS` and `YEAR_DAYS`
+    /// are used, keeping the simple calendar for anything unset or invalid.
+    fn from_env() -> Calendar {
+        if std::env::var("CALENDAR").is_ok_and(|calendar| calendar.eq_ignore_ascii_case("realistic")) {
+            return Calendar::REALISTIC;
+        }
+        let month_days = env_or("MONTH_DAYS", 30.0f64);
+        let year_days = env_or("YEAR_DAYS", 365.0f64);
+        if month_days >= 1.0 && year_days >= month_days {
+            Calendar::from_days(month_days, year_days)
+        } else {
+            log::warn!("Ignoring invalid MONTH_DAYS/YEAR_DAYS, using 30 and 365 days");
+            Calendar::SIMPLE
+        }
+    }
+}
+impl std::fmt::Display for GestationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            GestationType::Hours => "Hours".to_owned(),
-            GestationType::Days => "Days".to_owned(),
-            GestationType::Weeks => "Weeks".to_owned(),
-            GestationType::Months => "Months".to_owned(),
-            GestationType::Mins => "Mins".to_owned(),
+            GestationType::Hours => write!(f, "Hours"),
+            GestationType::Days => write!(f, "Days"),
+            GestationType::Weeks => write!(f, "Weeks"),
+            GestationType::Months => write!(f, "Months"),
+            GestationType::Mins => write!(f, "Mins"),
         }
     }
 }
@@ -136,6 +329,45 @@ impl TryFrom<u8> for GestationType {
         }
     }
 }
+/// Reads a Childcount parameter sent as an Int or a Float.
+/// Floats are rounded and out-of-range values clamped to `0..=255`.
+fn child_count_from_osc(value: &OscType) -> Option<u8> {
+    match *value {
+        OscType::Int(value) => Some(value.clamp(0, u8::MAX.into()) as u8),
+        OscType::Long(value) => Some(value.clamp(0, u8::MAX.into()) as u8),
+        OscType::Float(value) if value.is_finite() => Some(value.round().clamp(0.0, u8::MAX.into()) as u8),
+        OscType::Double(value) if value.is_finite() => Some(value.round().clamp(0.0, u8::MAX.into()) as u8),
+        _ => None,
+    }
+}
+/// Reads a GestationTime parameter sent as an Int or a Float.
+/// Only finite values above zero are a usable gestation time.
+fn gestation_time_from_osc(value: &OscType) -> Option<f32> {
+    let value = match *value {
+        OscType::Int(value) => value as f32,
+        OscType::Long(value) => value as f32,
+        OscType::Float(value) => value,
+        OscType::Double(value) => value as f32,
+        _ => return None,
+    };
+    Some(value).filter(|value| value.is_finite() && *value > 0.0)
+}
+/// Reads a Gestation parameter sent as an Int or a Float (e.g. from a radial puppet).
+/// Floats are rounded and out-of-range values clamped to the nearest valid unit.
+fn gestation_from_osc(value: &OscType) -> Option<GestationType> {
+    let value = match *value {
+        OscType::Int(value) => value.into(),
+        OscType::Long(value) => value as f64,
+        OscType::Float(value) => value.into(),
+        OscType::Double(value) => value,
+        _ => return None,
+    };
+    if !value.is_finite() {
+        return None;
+    }
+    let max = GestationType::iter().count() as f64 - 1.0;
+    GestationType::try_from(value.round().clamp(0.0, max) as u8).ok()
+}
 impl From<GestationType> for i32 {
     fn from(value: GestationType) -> Self {
         value as i32
@@ -150,6 +382,129 @@ impl From<GestationType> for u8 {
 lazy_static! {
     static ref SystemActive: Arc<Mutex<Option<bool>>> = Arc::new(Mutex::new(Some(false)));
     static ref ChildData: Arc<Mutex<Option<ChildInfo>>> = Arc::new(Mutex::new(None));
+    static ref CurrentAvatar: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    /// Set while the current avatar has a fertility system but is marked as not managed.
+    static ref UnmanagedDetected: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    static ref SaveDirty: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    static ref SendInterval: Arc<(Mutex<std::time::Duration>, Condvar)> = Arc::new((
+        Mutex::new(std::time::Duration::from_secs(5)),
+        Condvar::new()
+    ));
+    static ref LastIsPregnantSent: Arc<Mutex<Option<(bool, Instant)>>> = Arc::new(Mutex::new(None));
+    /// Set after `PregnancyBirth` goes out true; the next progress tick resets it.
+    static ref BirthPulse: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    /// Largest units shown in remaining times, 0 for all of them.
+    static ref DisplayUnits: Arc<Mutex<usize>> = Arc::new(Mutex::new(env_or("DISPLAY_UNITS", 0usize)));
+    static ref AvatarChanges: ChangeCoalescer = ChangeCoalescer::default();
+    /// How long to wait for more `/avatar/change` messages before acting on one.
+    static ref AvatarChangeWindow: std::time::Duration =
+        std::time::Duration::from_millis(env_or("AVATAR_CHANGE_DEBOUNCE_MS", 250u64));
+    static ref LastResync: Mutex<ResyncDebounce> = Mutex::new(ResyncDebounce::default());
+    /// `PregnancySave` value sent instead of the real one while the preview slider is held.
+    static ref ProgressPreview: Arc<Mutex<Option<f64>>> = Arc::new(Mutex::new(None));
+    /// Keeps avatar checks from interleaving.
+    static ref AvatarCheck: Mutex<()> = Mutex::new(());
+    /// Send the `PregnancyAppActive` heartbeat (`APP_ACTIVE_HEARTBEAT`).
+    static ref AppActiveHeartbeat: bool = env_or("APP_ACTIVE_HEARTBEAT", false);
+    static ref ActiveCalendar: Calendar = Calendar::from_env();
+    /// Start a fresh pregnancy every time an avatar is loaded (`AUTO_RESET_ON_LOAD`).
+    static ref AutoResetOnLoad: bool = env_or("AUTO_RESET_ON_LOAD", false);
+    /// Children added to an ongoing pregnancy get their own conception time
+    /// (`CONCURRENT_PREGNANCIES`), so each one is due on its own.
+    static ref ConcurrentPregnancies: bool = env_or("CONCURRENT_PREGNANCIES", false);
+    /// Highest `PregnancySave` value sent while overdue; 1.0 keeps the usual cap.
+    static ref OverdueCap: Arc<Mutex<f64>> = Arc::new(Mutex::new(env_or("OVERDUE_GROWTH_CAP", 1.0f64).max(1.0)));
+    /// Children needed before a pregnancy starts (`MIN_CHILDREN_TO_START`).
+    static ref MinChildren: Arc<Mutex<u8>> = Arc::new(Mutex::new(env_or("MIN_CHILDREN_TO_START", 1u8).max(1)));
+    /// Keep the latest values received while the system is inactive (`BUFFER_INACTIVE`).
+    static ref BufferInactive: bool = env_or("BUFFER_INACTIVE", false);
+    /// Values buffered for the avatar an unfinished check is about; `None` once it concludes.
+    static ref Pending: Arc<Mutex<Option<PendingValues>>> = Arc::new(Mutex::new(Some(PendingValues::default())));
+    /// Address overrides of the active avatar.
+    static ref AddressOverrides: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(HashMap::new()));
+}
+const PARAMETER_PREFIX: &str = "/avatar/parameters/";
+/// Child count parameter. The avatar reports it and is sent it at the same address.
+const CHILD_COUNT: &str = "Childcount";
+/// Address of parameter `name` under the given overrides.
+fn resolve_address(overrides: &HashMap<String, String>, name: &str) -> String {
+    overrides
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| format!("{}{}", PARAMETER_PREFIX, name))
+}
+/// Maps an incoming address back to the default address of the parameter it carries, so
+/// the receive match only deals with default names. Returns `None` for the default address
+/// of a parameter that has been moved elsewhere.
+fn canonical_address(overrides: &HashMap<String, String>, addr: &str) -> Option<String> {
+    if let Some((name, _)) = overrides.iter().find(|(_, address)| address.as_str() == addr) {
+        return Some(format!("{}{}", PARAMETER_PREFIX, name));
+    }
+    match addr.strip_prefix(PARAMETER_PREFIX) {
+        Some(name) if overrides.contains_key(name) => None,
+        _ => Some(addr.to_string()),
+    }
+}
+/// Whether `addr` is the default address of parameter `name`.
+fn is_parameter(addr: &str, name: &str) -> bool {
+    addr.strip_prefix(PARAMETER_PREFIX) == Some(name)
+}
+/// OSCQuery JSON pointer of a parameter address, relative to the `/avatar/parameters` node.
+fn oscquery_path(address: &str) -> Option<String> {
+    let name = address.strip_prefix(PARAMETER_PREFIX)?;
+    Some(name.split('/').map(|segment| format!("/CONTENTS/{}", segment)).collect())
+}
+/// Parameter addresses of the fertility system on an avatar.
+struct FertilitySchema {
+    overrides: HashMap<String, String>,
+}
+impl FertilitySchema {
+    /// Schema of the active avatar, including its address overrides.
+    fn active() -> Self {
+        FertilitySchema {
+            overrides: AddressOverrides.lock().unwrap().clone(),
+        }
+    }
+    fn address(&self, name: &str) -> String {
+        resolve_address(&self.overrides, name)
+    }
+}
+/// Parameters sent when an avatar with a fertility system is loaded:
+/// `GestationTime` and `Gestation` always, `Childcount` once there are children,
+/// `IsPregnant` while the pregnancy is running and `PregnancyOverdue` once it is past due.
+fn params_to_send(
+    child: &ChildInfo,
+    schema: &FertilitySchema,
+    now: DateTime<Local>,
+) -> Vec<(String, Vec<OscType>)> {
+    let mut params = vec![
+        (schema.address("GestationTime"), vec![OscType::Float(child.gestation_time)]),
+        (schema.address("Gestation"), vec![OscType::Int(child.gestation.into())]),
+    ];
+    if child.number_of_childern == 0 {
+        return params;
+    }
+    params.push((
+        schema.address(CHILD_COUNT),
+        vec![OscType::Int(child.number_of_childern.into())],
+    ));
+    if pregnancy_phase(child, now) == PregnancyPhase::Active {
+        params.push((schema.address("IsPregnant"), vec![OscType::Bool(true)]));
+        let overdue = child
+            .conception_time
+            .is_some_and(|conception| now > add_gestation(conception, gestation_duration_secs(child)));
+        if overdue {
+            params.push((schema.address("PregnancyOverdue"), vec![OscType::Bool(true)]));
+        }
+    }
+    params
+}
+/// Address of parameter `name` on the active avatar.
+fn param_address(name: &str) -> String {
+    resolve_address(&AddressOverrides.lock().unwrap(), name)
+}
+fn set_address_overrides(overrides: HashMap<String, String>) {
+    *AddressOverrides.lock().unwrap() = overrides;
 }
 pub struct PregancyHandler;
 
@@ -159,34 +514,89 @@ impl PacketHandler for PregancyHandler {
             OscPacket::Message(msg) => {
                 let (_osc_type, osc_value) = OscServer::auto_convert(&format!("{:?}", msg.args))
                     .unwrap_or((ValueType::Unknown, format!("{:?}", msg.args)));
-                match msg.addr.as_str() {
-                    "/avatar/parameters/Childcount" => {
-                        if get_system_active().unwrap() {
-                            child_counter(osc_value.parse::<u8>().unwrap());
-                            save_data().unwrap();
+                let value_type = msg.args.first().map_or(ValueType::Unknown, ValueType::from);
+                let Some(addr) = canonical_address(&AddressOverrides.lock().unwrap(), &msg.addr) else {
+                    return;
+                };
+                if !get_system_active().unwrap() && *BufferInactive {
+                    buffer_pending_value(&addr, msg.args.first());
+                }
+                match addr.as_str() {
+                    addr if is_parameter(addr, CHILD_COUNT) && get_system_active().unwrap() => {
+                        match msg.args.first().and_then(child_count_from_osc) {
+                            Some(count) => {
+                                child_counter(count);
+                                save_data().unwrap();
+                            }
+                            None => log::warn!("Ignoring invalid Childcount value {:?}", msg.args),
                         }
                     }
                     "/avatar/parameters/GestationTime" => {
                         log::debug!("Hitting gestationTime parameter");
                         if get_system_active().unwrap() {
-                            set_gestation_time(osc_value.parse::<f32>().unwrap());
-                            save_data().unwrap();
+                            match msg.args.first().and_then(gestation_time_from_osc) {
+                                Some(gestation_time) => {
+                                    set_gestation_time(gestation_time);
+                                    save_data().unwrap();
+                                }
+                                None => log::warn!("Ignoring invalid GestationTime value {:?}", msg.args),
+                            }
                         }
                     }
                     "/avatar/parameters/Gestation" => {
                         log::debug!("Hitting gestation parameter");
                         if get_system_active().unwrap() {
-                            set_gestation_type(osc_value.parse::<u8>().unwrap());
-                            save_data().unwrap();
+                            match msg.args.first().and_then(gestation_from_osc) {
+                                Some(gestation) => {
+                                    set_gestation_type(gestation.into());
+                                    save_data().unwrap();
+                                }
+                                None => log::warn!("Ignoring invalid Gestation value {:?}", msg.args),
+                            }
+                        }
+                    }
+                    "/avatar/parameters/IsPregnant" => {
+                        log::debug!("Hitting IsPregnant parameter");
+                        if get_system_active().unwrap() && value_type == ValueType::Bool {
+                            if let Ok(value) = osc_value.parse::<bool>() {
+                                is_pregnant_received(value);
+                            }
                         }
                     }
-                    "/avatar/change" => check_avatar_oscquery().unwrap(),
+                    "/avatar/change" => {
+                        // Swapping quickly sends a burst of changes; only the last one is checked.
+                        let change = AvatarChanges.begin();
+                        begin_avatar_change(msg.args.first().cloned().and_then(OscType::string));
+                        std::thread::spawn(move || {
+                            std::thread::sleep(*AvatarChangeWindow);
+                            if !AvatarChanges.is_current(change) {
+                                log::debug!("Skipping superseded avatar change");
+                                return;
+                            }
+                            // A real swap must never be answered from the previous avatar's cache.
+                            get_osc_query_cache().force_clear_avatar();
+                            if let Err(e) = check_avatar(*AutoResetOnLoad) {
+                                log::error!("Failed to check avatar after change: {}", e);
+                            }
+                        });
+                    }
                     _ => {}
                 }
             }
             OscPacket::Bundle(_bundle) => { /* println!("OSC Bundle: {:?}", bundle); */ }
         }
     }
+    fn on_connect(&self) {
+        if !LastResync.lock().unwrap().try_begin(Instant::now()) {
+            log::debug!("Skipping resync, one ran moments ago");
+            return;
+        }
+        std::thread::spawn(|| {
+            // Give the avatar a moment to load before pushing its parameters.
+            std::thread::sleep(std::time::Duration::from_secs(2));
+            resync_state();
+        });
+    }
     fn start(&self) {
         let native_options = eframe::NativeOptions {
             viewport: egui::ViewportBuilder::default()
@@ -199,461 +609,4105 @@ impl PacketHandler for PregancyHandler {
             native_options,
             Box::new(|_cc| Ok(Box::new(PregUI::new(_cc)))),
         );
+        set_send_interval(std::time::Duration::from_secs_f32(
+            env_or("SEND_INTERVAL_SECS", 5f32).max(MIN_SEND_INTERVAL_SECS),
+        ));
+        apply_settings(&load_settings());
+        let mut sender = ProgressSender {
+            send_next_child: env_or("SEND_NEXT_CHILD", false),
+            send_bundle: env_or("SEND_PROGRESS_BUNDLE", false),
+            smooth_steps: env_or("SMOOTH_STEPS", 1u32).max(1),
+            last_sent: None,
+        };
         // Spawn UI in separate thread
         std::thread::spawn(move || loop {
-            if get_system_active().unwrap() {
-                if get_child_count() > 0 {
-                    OscServer::send_osc_data(
-                        "/avatar/parameters/PregnancySave".to_string(),
-                        vec![OscType::Float(get_gestation_progress_fraction() as f32)],
-                    );
-                    log::debug!(
-                        "Current Pregnacy Progress is {}",
-                        get_gestation_progress_fraction()
-                    );
-                }
-                std::thread::sleep(std::time::Duration::from_secs(5));
+            sender.tick();
+            wait_send_interval(sender.smooth_steps);
+        });
+        #[cfg(feature = "discord")]
+        std::thread::spawn(|| loop {
+            let text = if discord_presence::is_enabled() && get_system_active().unwrap_or(false) {
+                let child = get_child_data().unwrap_or_default();
+                discord_presence::presence_text(
+                    child.number_of_childern,
+                    get_gestation_progress_fraction(),
+                    child.conception_time.map(|conception| {
+                        add_gestation(conception, gestation_duration_secs(&child)) - Local::now()
+                    }),
+                )
             } else {
+                None
+            };
+            discord_presence::update(text);
+            std::thread::sleep(std::time::Duration::from_secs(15));
+        });
+        #[cfg(feature = "local_api")]
+        if local_api::is_enabled() {
+            local_api::set_enabled(true, current_state_json);
+        }
+        #[cfg(feature = "notifications")]
+        std::thread::spawn(|| {
+            let mut notifier = notifications::Notifier::new();
+            let mut last_avatar = None;
+            loop {
+                // Progress of a different avatar is not a crossing.
+                let avatar = get_current_avatar();
+                if avatar != last_avatar {
+                    notifier.update(None);
+                    last_avatar = avatar;
+                }
+                let child = get_child_data().unwrap_or_default();
+                let pregnant = get_system_active().unwrap_or(false)
+                    && pregnancy_phase(&child, Local::now()) == PregnancyPhase::Active;
+                notifier.update(pregnant.then(get_gestation_progress_fraction));
                 std::thread::sleep(std::time::Duration::from_secs(5));
             }
         });
-        check_avatar_oscquery().unwrap();
+        if *AppActiveHeartbeat {
+            let heartbeat_secs = env_or("APP_ACTIVE_SECS", 5u64).max(1);
+            std::thread::spawn(move || loop {
+                app_active_tick();
+                std::thread::sleep(std::time::Duration::from_secs(heartbeat_secs));
+            });
+        }
+        let label_address = std::env::var("LABEL_ADDRESS").unwrap_or_default();
+        if !label_address.is_empty() {
+            let label_secs = env_or("LABEL_SECS", 10u64).max(1);
+            std::thread::spawn(move || loop {
+                let child = get_child_data().unwrap_or_default();
+                if get_system_active().unwrap_or(false) && child.number_of_childern > 0 {
+                    if let Some(conception) = child.conception_time {
+                        let due = add_gestation(conception, gestation_duration_secs(&child));
+                        OscServer::send_osc_data(
+                            label_address.clone(),
+                            vec![OscType::String(remaining_label(due - Local::now()))],
+                        );
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_secs(label_secs));
+            });
+        }
+        let autosave_secs = env_or("AUTOSAVE_SECS", 30u64);
+        if autosave_secs > 0 {
+            std::thread::spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_secs(autosave_secs));
+                autosave_tick();
+            });
+        }
+        let poll_secs = env_or("AVATAR_POLL_SECS", 5u64);
+        if poll_secs > 0 {
+            std::thread::spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_secs(poll_secs));
+                poll_avatar_change();
+            });
+        }
+        // Without OSCQuery (e.g. the UDP fallback) this only finds no avatar.
+        if let Err(e) = check_avatar_oscquery() {
+            log::error!("Failed to check the avatar on startup: {}", e);
+        }
     }
 }
-fn check_avatar_oscquery() -> Result<(), Box<dyn std::error::Error>> {
-    let data = get_osc_query_cache().get_avatar_parameters()?;
-    get_osc_query_cache().clear_avatar();
-    info!("Calling check avatar");
-    if json_path_exists(&data, "/CONTENTS/PregnancySave") {
-        info!("Found Fertility system on avatar");
-        let mut data = read_data()?;
-        // Set my childInfo data if we have data from our appdata directory, otherwise set a default childInfo.
-        set_child_data(
-            *data
-                .avatar_ids
-                .entry(
-                    get_osc_query_cache()
-                        .get_avatar_id()
-                        .unwrap()
-                        .expect("Missing string"),
-                )
-                .or_insert(ChildInfo {
-                    conception_time: None,
-                    gestation_time: 8f32,
-                    gestation: GestationType::Hours,
-                    number_of_childern: 0,
-                }),
-        );
-        set_system_active(true);
-        // Extract all needed data before spawning the async block to avoid holding MutexGuard across await.
-        let gestation_time = get_gestation_time();
-        let gestation_type = get_gestation_type();
-        let child_count = get_child_count();
-        OscServer::send_osc_data(
-            "/avatar/parameters/GestationTime".to_string(),
-            vec![OscType::Float(gestation_time.into())],
-        );
-        OscServer::send_osc_data(
-            "/avatar/parameters/Gestation".to_string(),
-            vec![OscType::Int(gestation_type.into())],
-        );
-        if child_count > 0 {
-            OscServer::send_osc_data(
-                "/avatar/parameters/ChildCount".to_string(),
-                vec![OscType::Int(child_count.into())],
-            );
-
-            OscServer::send_osc_data(
-                "/avatar/parameters/IsPregnant".to_string(),
-                vec![OscType::Bool(true)],
-            );
+/// Shortest allowed delay between progress sends.
+const MIN_SEND_INTERVAL_SECS: f32 = 0.5;
+fn get_send_interval() -> std::time::Duration {
+    *SendInterval.0.lock().unwrap()
+}
+/// Changes the progress send interval and wakes the sender so the new value applies right away.
+fn set_send_interval(value: std::time::Duration) {
+    let (lock, cvar) = &**SendInterval;
+    *lock.lock().unwrap() = value;
+    cvar.notify_all();
+}
+/// Sleeps until `1 / steps` of the send interval has passed since the call started.
+/// Re-checks whenever the interval changes, so shortening it ends a long wait early.
+fn wait_send_interval(steps: u32) {
+    let start = Instant::now();
+    let (lock, cvar) = &**SendInterval;
+    let mut interval = lock.lock().unwrap();
+    while let Some(remaining) = (*interval / steps).checked_sub(start.elapsed()) {
+        if remaining.is_zero() {
+            break;
         }
-
-        save_data_writer(&data)?;
-    } else {
-        set_system_active(false);
-        clear_child_data();
+        interval = cvar.wait_timeout(interval, remaining).unwrap().0;
     }
-    Ok(())
 }
-
-fn save_data_writer(data: &SaveData) -> std::io::Result<()> {
-    let json = serde_json::to_string_pretty(data).expect("Failed to serialize data");
-    let path = get_save_path().join("save_data.json");
-    let mut file = std::fs::File::create(path)?;
-    file.write_all(json.as_bytes())?;
-    Ok(())
+/// How long after sending `IsPregnant` an identical incoming value is treated as our own echo.
+const ECHO_WINDOW: std::time::Duration = std::time::Duration::from_secs(2);
+fn send_is_pregnant(value: bool) {
+    *LastIsPregnantSent.lock().unwrap() = Some((value, Instant::now()));
+    OscServer::send_osc_data(
+        param_address("IsPregnant"),
+        vec![OscType::Bool(value)],
+    );
 }
-
-fn save_data() -> std::io::Result<()> {
-    let mut save_data = read_data().unwrap();
-    let child_data = save_data
-        .avatar_ids
-        .get_mut(
-            &get_osc_query_cache()
-                .get_avatar_id()
-                .unwrap()
-                .expect("Missing avatar id"),
-        )
-        .unwrap();
-    *child_data = get_child_data().unwrap();
-    save_data_writer(&save_data).unwrap();
-    Ok(())
+/// Whether `received` is just VRChat reflecting the value we sent moments ago.
+fn is_echo(last_sent: Option<(bool, Instant)>, received: bool, now: Instant) -> bool {
+    last_sent.is_some_and(|(sent, at)| sent == received && now.duration_since(at) < ECHO_WINDOW)
 }
-
-fn read_data() -> std::io::Result<SaveData> {
-    let path = get_save_path().join("save_data.json");
-
-    // Check if file exists, if not create it with default SaveData
-    if !path.exists() {
-        let default_data = SaveData::default(); // Requires SaveData to implement Default
-        let json = serde_json::to_string_pretty(&default_data)
-            .expect("Failed to serialize default SaveData");
-        let mut file = std::fs::File::create(&path)?;
-        file.write_all(json.as_bytes())?;
+/// Handles `IsPregnant` set by the avatar or another tool.
+/// `false` ends the current pregnancy so the app follows the external reset. Children
+/// that have not started a pregnancy yet (e.g. below `MIN_CHILDREN_TO_START`) are kept.
+fn is_pregnant_received(value: bool) {
+    let child = get_child_data().unwrap_or_default();
+    if value || pregnancy_phase(&child, Local::now()) != PregnancyPhase::Active {
+        return;
+    }
+    if is_echo(*LastIsPregnantSent.lock().unwrap(), value, Instant::now()) {
+        log::debug!("Ignoring IsPregnant echo");
+        return;
+    }
+    info!("IsPregnant turned off externally, clearing pregnancy");
+    record_if_completed();
+    set_child_count(0);
+    clear_conception_time();
+    if let Err(e) = save_data() {
+        log::error!("Failed to save after an external reset: {}", e);
     }
-
-    let content = std::fs::read_to_string(path)?;
-    let data: SaveData = serde_json::from_str(&content).expect("Failed to deserialize JSON");
-    Ok(data)
 }
-
-fn child_counter(value: u8) {
-    if value > get_child_count() {
-        set_child_count(value);
-        if get_conception_time() == None {
-            set_conception_time(Local::now());
-        }
+/// Text for avatar displays, e.g. "Due in 2 days, 3 hours" or "Overdue by 5 minutes".
+fn remaining_label(remaining: chrono::Duration) -> String {
+    if remaining > chrono::Duration::zero() {
+        format!("Due in {}", format_remaining(remaining))
+    } else if remaining < chrono::Duration::zero() {
+        format!("Overdue by {}", format_remaining(-remaining))
+    } else {
+        "Due now".to_string()
     }
 }
-
-fn get_system_active() -> Option<bool> {
-    *SystemActive.lock().unwrap()
+/// Value sent for a pregnancy `overdue_secs` past its due date. It keeps growing from 1.0
+/// at the gestation rate up to `cap`, so a cap of 1.0 (the default) stays at 1.0.
+fn overdue_progress(overdue_secs: f64, total_duration_secs: f64, cap: f64) -> f64 {
+    if cap <= 1.0 || overdue_secs <= 0.0 || total_duration_secs <= 0.0 {
+        return 1.0;
+    }
+    (1.0 + overdue_secs / total_duration_secs).min(cap)
 }
-
-fn set_system_active(value: bool) {
-    *SystemActive.lock().unwrap() = Some(value);
+/// Largest progress increase sent per smoothing step.
+const MAX_SMOOTH_STEP: f64 = 0.02;
+/// Periodically sends the pregnancy progress to the avatar.
+struct ProgressSender {
+    send_next_child: bool,
+    send_bundle: bool,
+    /// Sends per send interval. Above 1, progress is smoothed between sends.
+    smooth_steps: u32,
+    last_sent: Option<f64>,
 }
-fn get_child_data() -> Option<ChildInfo> {
-    ChildData.lock().unwrap().clone()
+impl ProgressSender {
+    fn tick(&mut self) {
+        if std::mem::take(&mut *BirthPulse.lock().unwrap()) {
+            OscServer::send_osc_data(param_address("PregnancyBirth"), vec![OscType::Bool(false)]);
+        }
+        // While previewing, keep sending the preview value so the real one does not fight it.
+        if let Some(preview) = get_progress_preview() {
+            send_progress(preview);
+            return;
+        }
+        let now = Local::now();
+        start_scheduled_if_due(now);
+        let child = get_child_data().unwrap_or_default();
+        if !(get_system_active().unwrap() && pregnancy_phase(&child, now) == PregnancyPhase::Active) {
+            self.last_sent = None;
+            return;
+        }
+        let next = next_child_ready(&child_schedule(
+            &child_conception_times(&child),
+            gestation_duration_secs(&child),
+            now,
+        ));
+        let true_progress = match next {
+            Some(next) if now > next.due_date => overdue_progress(
+                (now - next.due_date).num_seconds() as f64,
+                gestation_duration_secs(&child),
+                get_overdue_cap(),
+            ),
+            Some(next) => next.progress,
+            None => 0f64,
+        };
+        let progress = match self.last_sent {
+            Some(last) if self.smooth_steps > 1 => {
+                smooth_progress(last, true_progress, MAX_SMOOTH_STEP)
+            }
+            _ => true_progress,
+        };
+        self.last_sent = Some(progress);
+        if self.send_bundle {
+            let overdue = next.is_some_and(|next| now > next.due_date);
+            OscServer::send_osc_bundle(progress_bundle(
+                progress,
+                child.number_of_childern,
+                overdue,
+            ));
+        } else {
+            OscServer::send_osc_data(
+                param_address("PregnancySave"),
+                vec![OscType::Float(progress as f32)],
+            );
+        }
+        if self.send_next_child {
+            if let Some(next) = next {
+                OscServer::send_osc_data(
+                    param_address("NextChild"),
+                    vec![OscType::Int(next.index as i32)],
+                );
+            }
+        }
+        log::debug!("Current Pregnacy Progress is {}", true_progress);
+    }
 }
-fn set_child_data(value: ChildInfo) {
-    let mut lock = ChildData.lock().unwrap();
-    *lock = Some(value);
+/// Numbers avatar changes so work started for an older change can tell it was superseded.
+#[derive(Default)]
+struct ChangeCoalescer {
+    latest: std::sync::atomic::AtomicU64,
 }
-fn clear_child_data() {
-    let mut lock = ChildData.lock().unwrap();
-    *lock = None;
+impl ChangeCoalescer {
+    /// Registers a new change and returns its number.
+    fn begin(&self) -> u64 {
+        self.latest.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    }
+    fn current(&self) -> u64 {
+        self.latest.load(std::sync::atomic::Ordering::SeqCst)
+    }
+    /// Whether `change` is still the latest one.
+    fn is_current(&self, change: u64) -> bool {
+        self.current() == change
+    }
 }
-fn get_child_count() -> u8 {
-    let childdata: ChildInfo = get_child_data().unwrap_or_default();
-    return childdata.number_of_childern;
+/// How long after a connect resync further connect events are ignored.
+const RESYNC_DEBOUNCE: std::time::Duration = std::time::Duration::from_secs(10);
+/// Lets only one connect-triggered resync through per `RESYNC_DEBOUNCE`.
+#[derive(Default)]
+struct ResyncDebounce {
+    last: Option<Instant>,
 }
-fn set_child_count(value: u8) {
-    let mut lock = ChildData.lock().unwrap();
-    if let Some(ref mut childdata) = *lock {
-        childdata.number_of_childern = value;
+impl ResyncDebounce {
+    /// Returns whether a resync should run at `now`, and if so marks it as started.
+    fn try_begin(&mut self, now: Instant) -> bool {
+        if self.last.is_some_and(|last| now.duration_since(last) < RESYNC_DEBOUNCE) {
+            return false;
+        }
+        self.last = Some(now);
+        true
     }
-    OscServer::send_osc_data("/avatar/parameters/ChildCount".to_string(), vec![OscType::Int(value.into())]);
 }
-fn get_conception_time() -> Option<DateTime<Local>> {
-    let childdata: ChildInfo = get_child_data().unwrap_or_default();
-    return childdata.conception_time;
+/// Every parameter the app manages, for pushing the full state to a freshly loaded avatar.
+fn state_bundle(child: &ChildInfo, progress: f64, pregnant: bool) -> Vec<rosc::OscMessage> {
+    vec![
+        rosc::OscMessage {
+            addr: param_address("GestationTime"),
+            args: vec![OscType::Float(child.gestation_time)],
+        },
+        rosc::OscMessage {
+            addr: param_address("Gestation"),
+            args: vec![OscType::Int(child.gestation.into())],
+        },
+        rosc::OscMessage {
+            addr: param_address(CHILD_COUNT),
+            args: vec![OscType::Int(child.number_of_childern.into())],
+        },
+        rosc::OscMessage {
+            addr: param_address("IsPregnant"),
+            args: vec![OscType::Bool(pregnant)],
+        },
+        rosc::OscMessage {
+            addr: param_address("PregnancySave"),
+            args: vec![OscType::Float(progress as f32)],
+        },
+    ]
 }
-fn clear_conception_time() {
-    let mut lock = ChildData.lock().unwrap();
-    if let Some(ref mut childdata) = *lock {
-        childdata.conception_time = None;
+/// Sends the active avatar's full state as one bundle.
+fn resync_state() {
+    let Some(child) = get_child_data().filter(|_| get_system_active().unwrap_or(false)) else {
+        return;
+    };
+    info!("Resyncing pregnancy state after VRChat connected");
+    let pregnant = pregnancy_phase(&child, Local::now()) == PregnancyPhase::Active;
+    // Recorded like `send_is_pregnant` so the echo is not taken as an external reset.
+    *LastIsPregnantSent.lock().unwrap() = Some((pregnant, Instant::now()));
+    OscServer::send_osc_bundle(state_bundle(&child, get_gestation_progress_fraction(), pregnant));
+}
+/// Whether the `PregnancyAppActive` heartbeat should go out: the app is managing
+/// an avatar and has a route to VRChat.
+fn heartbeat_due(active: bool, state: ConnectionState) -> bool {
+    active && matches!(state, ConnectionState::OscFound | ConnectionState::OscQueryConnected)
+}
+fn send_app_active(value: bool) {
+    OscServer::send_osc_data(param_address("PregnancyAppActive"), vec![OscType::Bool(value)]);
+}
+/// Sends the heartbeat when it is due.
+fn app_active_tick() {
+    if heartbeat_due(get_system_active().unwrap_or(false), OscServer::connection_state()) {
+        send_app_active(true);
     }
 }
-fn set_conception_time(value: DateTime<Local>) {
-    let mut lock = ChildData.lock().unwrap();
-    if let Some(ref mut childdata) = *lock {
-        childdata.conception_time = Some(value);
+/// Tells the avatar the app is going away, if the heartbeat told it the app was there.
+fn app_active_exit() {
+    if heartbeat_due(get_system_active().unwrap_or(false), OscServer::connection_state()) {
+        send_app_active(false);
+        // OSCQuery sends are queued on a runtime; give the last one a moment to go out.
+        std::thread::sleep(std::time::Duration::from_millis(200));
     }
 }
-fn get_gestation_time() -> f32 {
-    let childdata: ChildInfo = get_child_data().unwrap_or_default();
-    return childdata.gestation_time;
+/// Writes any change still waiting for the autosave, so nothing is lost on exit.
+fn flush_save() {
+    if autosave_tick() {
+        info!("Flushed unsaved changes before exit");
+    }
 }
-fn set_gestation_time(value: f32) {
-    let mut lock = ChildData.lock().unwrap();
-    if let Some(ref mut childdata) = *lock {
-        childdata.gestation_time = value;
+/// Cleans up before the app exits: flushes unsaved changes, clears the Discord presence
+/// and tells the avatar the app is gone when the heartbeat is on.
+pub fn shutdown() {
+    flush_save();
+    #[cfg(feature = "discord")]
+    discord_presence::clear();
+    if *AppActiveHeartbeat {
+        app_active_exit();
     }
 }
-fn get_gestation_type() -> GestationType {
-    let childdata: ChildInfo = get_child_data().unwrap_or_default();
-    return GestationType::try_from(childdata.gestation).unwrap_or(GestationType::Hours);
+/// Latest values received while the system was inactive, applied once it activates.
+/// Covers short gaps where the avatar was not recognised yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PendingValues {
+    /// Avatar from the `/avatar/change` the values arrived after, if known.
+    avatar_id: Option<String>,
+    child_count: Option<u8>,
+    gestation_time: Option<f32>,
+    gestation: Option<GestationType>,
 }
-fn set_gestation_type(value: u8) {
-    let mut lock = ChildData.lock().unwrap();
-    if let Some(ref mut childdata) = *lock {
-        childdata.gestation = GestationType::try_from(value).unwrap_or(GestationType::Hours);
+fn buffer_pending_value(addr: &str, value: Option<&OscType>) {
+    let mut pending = Pending.lock().unwrap();
+    let Some(pending) = pending.as_mut() else {
+        return;
+    };
+    match addr {
+        addr if is_parameter(addr, CHILD_COUNT) => {
+            pending.child_count = value.and_then(child_count_from_osc).or(pending.child_count);
+        }
+        "/avatar/parameters/GestationTime" => {
+            pending.gestation_time = value.and_then(gestation_time_from_osc).or(pending.gestation_time);
+        }
+        "/avatar/parameters/Gestation" => {
+            pending.gestation = value.and_then(gestation_from_osc).or(pending.gestation);
+        }
+        _ => return,
     }
+    log::debug!("Buffered {} while inactive", addr);
 }
-/// Calculates a future DateTime by adding a duration (in whole seconds)
-/// computed as multiplier * (seconds per unit).
-#[inline] // Hint to inline the function
-pub fn calculate_future_time() -> DateTime<Local> {
-    // Casting directly from f64 to i64 truncates the fractional part.
-    let total_duration_secs =
-        get_gestation_time() as f64 * get_gestation_type().seconds_per_unit() as f64;
-    let conception_time = get_conception_time();
-    if conception_time == None {
-        return Local::now();
+/// Applies buffered values to `child`. The child count only ever goes up, like `child_counter`.
+fn apply_pending(child: &mut ChildInfo, pending: PendingValues, min_children: u8, now: DateTime<Local>) {
+    if let Some(gestation_time) = pending.gestation_time {
+        child.gestation_time = gestation_time;
+    }
+    if let Some(gestation) = pending.gestation {
+        child.gestation = gestation;
+    }
+    if let Some(count) = pending.child_count.filter(|count| *count > child.number_of_childern) {
+        let phase = pregnancy_phase(child, now);
+        child.number_of_childern = count;
+        if conception_change(count, min_children, phase) == ConceptionChange::Start {
+            child.conception_time = Some(now);
+        }
     }
-    return conception_time.unwrap() + Duration::seconds(total_duration_secs as i64);
 }
-/// Returns the remaining percentage of gestation time as a decimal between 0.0 and 1.0
-#[inline] // Hint to inline the function
-pub fn get_gestation_progress_fraction() -> f64 {
-    if get_child_count() == 0 {
-        return 0f64;
+/// Starts buffering afresh for the avatar being changed to.
+fn reset_pending_values(avatar_id: Option<String>) {
+    *Pending.lock().unwrap() = Some(PendingValues {
+        avatar_id,
+        ..PendingValues::default()
+    });
+}
+/// Stops applying parameters to the previous avatar's state until the new avatar is checked.
+fn begin_avatar_change(avatar_id: Option<String>) {
+    autosave_tick();
+    set_system_active(false);
+    clear_child_data();
+    reset_pending_values(avatar_id);
+}
+/// Drops the buffered values once a check concludes without activating.
+fn discard_pending_values() {
+    *Pending.lock().unwrap() = None;
+}
+/// Applies and clears the values buffered while inactive, unless they were buffered
+/// for a different avatar than `avatar_id`. Returns whether any were applied.
+fn apply_pending_values(avatar_id: &str) -> bool {
+    let Some(pending) = Pending.lock().unwrap().take() else {
+        return false;
+    };
+    if pending.avatar_id.as_deref().is_some_and(|id| id != avatar_id) {
+        log::debug!("Dropping values buffered for avatar {:?}", pending.avatar_id);
+        return false;
     }
-    let total_duration_secs =
-        get_gestation_time() as f64 * get_gestation_type().seconds_per_unit() as f64;
-
-    let conception_time = get_conception_time();
-    if conception_time == None {
-        return 0f64;
+    if pending.child_count.is_none() && pending.gestation_time.is_none() && pending.gestation.is_none() {
+        return false;
     }
-
-    let elapsed_secs = (Local::now() - conception_time.unwrap()).num_seconds() as f64;
-    //log::debug!("elpased time: {}, total time: {}, returned fraction {}",elapsed_secs, total_duration_secs, elapsed_secs/total_duration_secs);
-    // remaining fraction as decimal
-    return (elapsed_secs / total_duration_secs).min(1.0);
+    let Some(mut child) = get_child_data() else {
+        return false;
+    };
+    info!("Applying values received while inactive: {:?}", pending);
+    apply_pending(&mut child, pending, get_min_children(), Local::now());
+    set_child_data(child);
+    set_save_dirty(true);
+    true
 }
-#[derive(Default)]
-pub struct PregUI {
-    last_content_size: egui::Vec2,
+/// Where the current pregnancy is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PregnancyPhase {
+    NotPregnant,
+    /// Armed to begin at a future conception time; nothing is sent until then.
+    Scheduled,
+    Active,
 }
-
-impl PregUI {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
-        // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
-        // for e.g. egui::PaintCallback.
-        Self::default()
+fn pregnancy_phase(child: &ChildInfo, now: DateTime<Local>) -> PregnancyPhase {
+    match child.conception_time {
+        // Children may still be added before a scheduled start.
+        Some(conception) if child.scheduled && conception > now => PregnancyPhase::Scheduled,
+        _ if child.number_of_childern == 0 => PregnancyPhase::NotPregnant,
+        None => PregnancyPhase::NotPregnant,
+        Some(_) => PregnancyPhase::Active,
     }
 }
-/// Formats a chrono::Duration into a human-readable string like:
+/// Arms a pregnancy to begin at `start`. Ignored while one is already running.
+fn schedule_conception(start: DateTime<Local>) {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        if pregnancy_phase(childdata, Local::now()) == PregnancyPhase::Active {
+            log::warn!("Not scheduling a start while a pregnancy is running");
+            return;
+        }
+        childdata.conception_time = Some(start);
+        childdata.scheduled = true;
+        set_save_dirty(true);
+    }
+}
+/// Starts a due scheduled pregnancy and returns whether the schedule was resolved.
+/// Without enough children by then the schedule lapses and the avatar is left not pregnant.
+fn start_scheduled_if_due(now: DateTime<Local>) -> bool {
+    let Some(child) = get_child_data() else {
+        return false;
+    };
+    if !child.scheduled || pregnancy_phase(&child, now) == PregnancyPhase::Scheduled {
+        return false;
+    }
+    if child.number_of_childern < get_min_children().max(1) {
+        info!("Scheduled start passed without enough children, schedule cleared");
+        set_child_data(ChildInfo {
+            scheduled: false,
+            conception_time: None,
+            ..child
+        });
+    } else {
+        info!("Scheduled pregnancy started");
+        set_child_data(ChildInfo {
+            scheduled: false,
+            ..child
+        });
+        send_is_pregnant(true);
+    }
+    set_save_dirty(true);
+    if let Err(e) = save_data() {
+        log::error!("Failed to save started pregnancy: {}", e);
+    }
+    true
+}
+fn send_progress(progress: f64) {
+    OscServer::send_osc_data(param_address("PregnancySave"), vec![OscType::Float(progress as f32)]);
+}
+/// Starts or moves the progress preview. Only the sent value changes; the pregnancy is untouched.
+fn set_progress_preview(progress: f64) {
+    let progress = progress.clamp(0.0, 1.0);
+    *ProgressPreview.lock().unwrap() = Some(progress);
+    send_progress(progress);
+}
+/// Ends the preview and sends the real progress again right away.
+fn end_progress_preview() {
+    if ProgressPreview.lock().unwrap().take().is_some() {
+        let active = get_system_active().unwrap_or(false) && get_child_count() > 0;
+        send_progress(if active { get_gestation_progress_fraction() } else { 0.0 });
+    }
+}
+fn get_progress_preview() -> Option<f64> {
+    *ProgressPreview.lock().unwrap()
+}
+/// Moves from the last sent value towards `target` by at most `max_step`.
+/// Never overshoots `target`, and drops straight to `target` when it goes down (e.g. a reset).
+/// Targets above 1.0 only come from the overdue growth cap.
+fn smooth_progress(last_sent: f64, target: f64, max_step: f64) -> f64 {
+    let target = target.max(0.0);
+    if target <= last_sent {
+        target
+    } else {
+        (last_sent + max_step).min(target)
+    }
+}
+/// Returns the trimester (1-3) for a progress fraction between 0.0 and 1.0.
+fn trimester(progress: f64) -> i32 {
+    if progress < 1.0 / 3.0 {
+        1
+    } else if progress < 2.0 / 3.0 {
+        2
+    } else {
+        3
+    }
+}
+/// Builds the progress snapshot sent as one bundle, in this order:
+/// `PregnancySave` (Float), `PregnancyTrimester` (Int), `Childcount` (Int), `PregnancyOverdue` (Bool).
+fn progress_bundle(progress: f64, child_count: u8, overdue: bool) -> Vec<rosc::OscMessage> {
+    vec![
+        rosc::OscMessage {
+            addr: param_address("PregnancySave"),
+            args: vec![OscType::Float(progress as f32)],
+        },
+        rosc::OscMessage {
+            addr: param_address("PregnancyTrimester"),
+            args: vec![OscType::Int(trimester(progress))],
+        },
+        rosc::OscMessage {
+            addr: param_address(CHILD_COUNT),
+            args: vec![OscType::Int(child_count.into())],
+        },
+        rosc::OscMessage {
+            addr: param_address("PregnancyOverdue"),
+            args: vec![OscType::Bool(overdue)],
+        },
+    ]
+}
+/// Re-reads the avatar id from OSCQuery and resyncs when it differs from the last checked avatar.
+/// Returns whether a resync was triggered.
+fn poll_avatar_change() -> bool {
+    let polled = match get_osc_query_cache().refresh_avatar_id() {
+        Ok(Some(id)) => id,
+        _ => return false,
+    };
+    if get_current_avatar().as_deref() == Some(polled.as_str()) {
+        return false;
+    }
+    info!("Avatar changed to {} without /avatar/change, resyncing", polled);
+    if let Err(e) = check_avatar(*AutoResetOnLoad) {
+        log::error!("Failed to resync avatar {}: {}", polled, e);
+    }
+    true
+}
+/// Extra parameter fetches after an avatar change before it counts as unsupported.
+const DETECT_RETRIES: u32 = 2;
+const DETECT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+/// Fetches the parameter tree up to `retries` more times, `delay` apart, until `detect`
+/// finds the fertility system. VRChat can serve a partial tree while an avatar loads.
+fn retry_detection<E>(
+    retries: u32,
+    delay: std::time::Duration,
+    mut fetch: impl FnMut() -> Result<serde_json::Value, E>,
+    detect: impl Fn(&serde_json::Value) -> bool,
+) -> Result<bool, E> {
+    for attempt in 1..=retries {
+        std::thread::sleep(delay);
+        if detect(&fetch()?) {
+            log::debug!("Fertility system found on retry {}", attempt);
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+/// Rechecks the current avatar, e.g. on startup or after its management changed.
+fn check_avatar_oscquery() -> Result<(), Box<dyn std::error::Error>> {
+    check_avatar(false)
+}
+/// Detects the fertility system on the current avatar and activates it.
+/// With `reset_on_load` (`AUTO_RESET_ON_LOAD` when the avatar was just loaded) a saved
+/// pregnancy is dropped instead of resumed.
+fn check_avatar(reset_on_load: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let _check = AvatarCheck.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let change = AvatarChanges.current();
+    let data = get_osc_query_cache().get_avatar_parameters()?;
+    get_osc_query_cache().clear_avatar();
+    info!("Calling check avatar");
+    let avatar_id = get_osc_query_cache().get_avatar_id().ok().flatten();
+    // The fetched data may belong to an avatar that was already swapped away from.
+    if !AvatarChanges.is_current(change) {
+        log::debug!("Avatar changed during check, leaving it to the newer check");
+        return Ok(());
+    }
+    let previous_avatar = get_current_avatar();
+    let swapped = avatar_id.is_some() && avatar_id != previous_avatar;
+    if swapped {
+        metrics::record_avatar_swap();
+    }
+    // Routing has to follow the avatar before anything is sent or matched for it.
+    let overrides = match avatar_id.as_deref().filter(|id| is_valid_avatar_id(id)) {
+        Some(id) => read_data()?.address_overrides.remove(id).unwrap_or_default(),
+        None => HashMap::new(),
+    };
+    set_address_overrides(overrides);
+    let save_path = oscquery_path(&param_address("PregnancySave"));
+    let detect = |data: &serde_json::Value| save_path.as_ref().is_some_and(|path| json_path_exists(data, path));
+    let mut fertility_detected = detect(&data);
+    if swapped && !fertility_detected {
+        fertility_detected = retry_detection(
+            DETECT_RETRIES,
+            DETECT_RETRY_DELAY,
+            || get_osc_query_cache().refetch_avatar_parameters(),
+            detect,
+        )?;
+        if !AvatarChanges.is_current(change) {
+            log::debug!("Avatar changed while waiting for its parameters, leaving it to the newer check");
+            return Ok(());
+        }
+    }
+    if swapped {
+        diagnostics::record_avatar_swap(diagnostics::AvatarSwap {
+            time: Local::now(),
+            from: previous_avatar,
+            to: avatar_id.clone(),
+            fertility_detected,
+        });
+    }
+    switch_avatar(avatar_id);
+    set_unmanaged_detected(false);
+    if fertility_detected {
+        info!("Found Fertility system on avatar");
+        let Some(avatar_id) = get_current_avatar().filter(|id| is_valid_avatar_id(id)) else {
+            log::warn!("Fertility system found but the avatar id is unknown, not activating");
+            discard_pending_values();
+            set_system_active(false);
+            clear_child_data();
+            return Ok(());
+        };
+        let mut data = read_data()?;
+        if !data.is_managed(&avatar_id) {
+            info!("Avatar {} is not managed, leaving its fertility system alone", avatar_id);
+            set_unmanaged_detected(true);
+            discard_pending_values();
+            set_system_active(false);
+            clear_child_data();
+            return Ok(());
+        }
+        let fresh = new_child_info(&data, &avatar_id);
+        // Set my childInfo data if we have data from our appdata directory, otherwise start from the configured defaults.
+        let (child, reset) = child_on_load(
+            data.avatar_ids.get(&avatar_id).cloned(),
+            fresh,
+            reset_on_load,
+        );
+        data.avatar_ids.insert(avatar_id.clone(), child.clone());
+        set_child_data(child.clone());
+        set_system_active(true);
+        if let Some(drift) = conception_drift(&child, Local::now()) {
+            log::warn!(
+                "Conception time {:?} looks wrong ({:?}), the system clock may have been off",
+                child.conception_time,
+                drift
+            );
+        }
+        if reset {
+            // Values the avatar reported before the check belong to the dropped pregnancy.
+            discard_pending_values();
+        }
+        if apply_pending_values(&avatar_id) {
+            if let Some(child) = get_child_data() {
+                data.avatar_ids.insert(avatar_id.clone(), child);
+            }
+        }
+        // Extract all needed data before sending to avoid holding the MutexGuard while sending.
+        let child = get_child_data().unwrap_or_default();
+        let schema = FertilitySchema::active();
+        for (addr, args) in params_to_send(&child, &schema, Local::now()) {
+            if addr == schema.address("IsPregnant") {
+                // Recorded like `send_is_pregnant` so the echo is not taken as an external reset.
+                *LastIsPregnantSent.lock().unwrap() = Some((true, Instant::now()));
+            }
+            OscServer::send_osc_data(addr, args);
+        }
+        if reset {
+            // The avatar may still hold the dropped pregnancy in its own saved parameters.
+            OscServer::send_osc_data(schema.address(CHILD_COUNT), vec![OscType::Int(0)]);
+            send_is_pregnant(false);
+        }
+
+        save_data_writer(&data)?;
+    } else {
+        discard_pending_values();
+        set_system_active(false);
+        clear_child_data();
+    }
+    Ok(())
+}
+
+fn save_data_writer(data: &SaveData) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(data).expect("Failed to serialize data");
+    let path = get_save_path().join("save_data.json");
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Writes the active avatar's in-memory state to disk and returns whether the file was written.
+/// Skips the write while the avatar id or child data is not known yet; the in-memory
+/// state is kept and saved by the next call once the avatar has been discovered.
+/// Also skips it when the stored entry already matches.
+fn save_data() -> std::io::Result<bool> {
+    let (avatar_id, child) = current_avatar_and_child();
+    let Some(avatar_id) = avatar_id.filter(|id| is_valid_avatar_id(id)) else {
+        log::warn!("Avatar id not known yet, skipping save");
+        return Ok(false);
+    };
+    let Some(child) = child else {
+        log::warn!("No child data loaded for avatar {}, skipping save", avatar_id);
+        return Ok(false);
+    };
+    #[cfg(debug_assertions)]
+    assert_invariants(&child);
+    let mut save_data = read_data()?;
+    // Cleared before writing so a change made during the write is picked up next time.
+    set_save_dirty(false);
+    if !child_info_changed(save_data.avatar_ids.get(&avatar_id), &child) {
+        return Ok(false);
+    }
+    save_data.avatar_ids.insert(avatar_id, child);
+    save_data_writer(&save_data).inspect_err(|_| set_save_dirty(true))?;
+    log::debug!("Saved child data");
+    Ok(true)
+}
+/// Lists the ways `child` is internally inconsistent; empty when it is valid.
+/// Only built in debug builds, where it runs whenever child data is stored or saved.
+#[cfg(debug_assertions)]
+fn check_invariants(child: &ChildInfo, now: DateTime<Local>) -> Vec<String> {
+    let mut violations = Vec::new();
+    if child.conception_time.is_some() && child.number_of_childern == 0 && !child.scheduled {
+        violations.push("conception time is set without any children".to_string());
+    }
+    if child.additional_conceptions.len() >= child.number_of_childern.max(1) as usize {
+        violations.push("more separate conceptions than children".to_string());
+    }
+    if child.scheduled && child.conception_time.is_none() {
+        violations.push("scheduled without a start time".to_string());
+    }
+    if !(child.gestation_time.is_finite() && child.gestation_time > 0.0) {
+        violations.push(format!("gestation time {} is not positive", child.gestation_time));
+    }
+    for entry in child_schedule(&child_conception_times(child), gestation_duration_secs(child), now) {
+        if !(0.0..=1.0).contains(&entry.progress) {
+            violations.push(format!("child {} progress {} is outside 0..=1", entry.index, entry.progress));
+        }
+    }
+    violations
+}
+/// Reports invariant violations, panicking instead of logging when `INVARIANTS_PANIC=true`.
+#[cfg(debug_assertions)]
+fn assert_invariants(child: &ChildInfo) {
+    let violations = check_invariants(child, Local::now());
+    if violations.is_empty() {
+        return;
+    }
+    let message = format!("Child data invariants violated: {} in {:?}", violations.join("; "), child);
+    if env_or("INVARIANTS_PANIC", false) {
+        panic!("{}", message);
+    }
+    log::error!("{}", message);
+}
+fn child_info_changed(stored: Option<&ChildInfo>, current: &ChildInfo) -> bool {
+    stored != Some(current)
+}
+/// Safety net for changes that were not saved right away.
+/// Returns whether there was anything to flush.
+fn autosave_tick() -> bool {
+    if !is_save_dirty() {
+        return false;
+    }
+    if let Err(e) = save_data() {
+        log::error!("Autosave failed: {}", e);
+    }
+    true
+}
+
+/// Adds the current pregnancy to the history when its children are removed after the due date.
+/// Removing them earlier is not counted; `give_birth` records early births.
+fn record_if_completed() {
+    let (Some(avatar_id), Some(child)) = current_avatar_and_child() else {
+        return;
+    };
+    let Some(conception_time) = child.conception_time else {
+        return;
+    };
+    if child.number_of_childern == 0 || get_gestation_progress_fraction() < 1.0 {
+        return;
+    }
+    record_pregnancy(PregnancyRecord {
+        avatar_id,
+        conception_time,
+        birth_time: Local::now(),
+        number_of_childern: child.number_of_childern,
+    });
+}
+fn record_pregnancy(record: PregnancyRecord) {
+    let result = read_data().and_then(|mut data| {
+        data.history.push(record);
+        save_data_writer(&data)
+    });
+    if let Err(e) = result {
+        log::error!("Failed to record pregnancy history: {}", e);
+    }
+}
+/// Ends the current pregnancy as a birth, whether or not it reached its due date.
+/// Records it in the history, updates the child data in one step, then sends
+/// `PregnancyBirth`, `Childcount` 0 and `IsPregnant` false.
+/// `PregnancyBirth` is a pulse: the next progress tick sends it back to false,
+/// so the avatar sees one rising edge per birth.
+fn give_birth() {
+    let (Some(avatar_id), Some(child)) = current_avatar_and_child() else {
+        return;
+    };
+    if child.number_of_childern == 0 {
+        return;
+    }
+    info!("Giving birth to {} children", child.number_of_childern);
+    if let Some(conception_time) = child.conception_time {
+        record_pregnancy(PregnancyRecord {
+            avatar_id,
+            conception_time,
+            birth_time: Local::now(),
+            number_of_childern: child.number_of_childern,
+        });
+    }
+    set_child_data(ChildInfo {
+        number_of_childern: 0,
+        conception_time: None,
+        scheduled: false,
+        additional_conceptions: Vec::new(),
+        ..child
+    });
+    set_save_dirty(true);
+    OscServer::send_osc_data(param_address("PregnancyBirth"), vec![OscType::Bool(true)]);
+    *BirthPulse.lock().unwrap() = true;
+    OscServer::send_osc_data(param_address(CHILD_COUNT), vec![OscType::Int(0)]);
+    send_is_pregnant(false);
+    if let Err(e) = save_data() {
+        log::error!("Failed to save after birth: {}", e);
+    }
+}
+/// Ends the pregnancy on every saved avatar while keeping their entries and settings.
+fn reset_all_pregnancies(data: &mut SaveData) {
+    for child in data.avatar_ids.values_mut() {
+        child.number_of_childern = 0;
+        child.conception_time = None;
+        child.scheduled = false;
+        child.additional_conceptions.clear();
+    }
+}
+/// Clears every avatar's pregnancy on disk and resets the active avatar's parameters.
+fn reset_all_avatars() -> std::io::Result<()> {
+    info!("Resetting pregnancy on all avatars");
+    if get_child_data().is_some() {
+        set_child_count(0);
+        clear_conception_time();
+        send_is_pregnant(false);
+    }
+    let mut data = read_data()?;
+    reset_all_pregnancies(&mut data);
+    save_data_writer(&data)?;
+    set_save_dirty(false);
+    Ok(())
+}
+/// Stores the gestation settings new avatars should start with.
+fn save_default_gestation(defaults: GestationDefaults) -> std::io::Result<()> {
+    let mut save_data = read_data()?;
+    save_data.default_gestation = defaults;
+    save_data_writer(&save_data)
+}
+
+fn read_data() -> std::io::Result<SaveData> {
+    let path = get_save_path().join("save_data.json");
+
+    // Check if file exists, if not create it with default SaveData
+    if !path.exists() {
+        let default_data = SaveData::default(); // Requires SaveData to implement Default
+        let json = serde_json::to_string_pretty(&default_data)
+            .expect("Failed to serialize default SaveData");
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(json.as_bytes())?;
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let (mut data, migrated) = match parse_save_data(&content) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            // Moved aside like a corrupt settings file, so the next save starts clean.
+            log::error!("Save file {} is corrupt, using defaults: {}", path.display(), e);
+            if let Err(e) = std::fs::rename(&path, path.with_extension("json.bak")) {
+                log::error!("Failed to move corrupt save file aside: {}", e);
+            }
+            return Ok(SaveData::default());
+        }
+    };
+    if migrated {
+        info!("Converted legacy save data to the current format");
+    }
+    if remove_invalid_avatar_ids(&mut data) || migrated {
+        save_data_writer(&data)?;
+    }
+    Ok(data)
+}
+/// Legacy save layout: a flat object mapping avatar ids straight to their child data,
+/// without the `avatar_ids` wrapper or any of the later settings.
+type LegacySaveData = HashMap<String, ChildInfo>;
+/// Parses a save file in the current or the legacy layout.
+/// Returns the data and whether it was converted from the legacy layout.
+/// A file is only treated as legacy when it has no `avatar_ids` key and every entry is child data.
+fn parse_save_data(content: &str) -> serde_json::Result<(SaveData, bool)> {
+    let json: serde_json::Value = serde_json::from_str(content)?;
+    if json.get("avatar_ids").is_none() {
+        if let Ok(legacy) = serde_json::from_value::<LegacySaveData>(json.clone()) {
+            let data = SaveData {
+                avatar_ids: legacy,
+                ..SaveData::default()
+            };
+            return Ok((data, true));
+        }
+    }
+    Ok((serde_json::from_value(json)?, false))
+}
+/// Avatar ids are used as save keys, so blank ones would collect junk state.
+fn is_valid_avatar_id(id: &str) -> bool {
+    !id.trim().is_empty()
+}
+/// Drops entries saved under blank avatar ids by older versions.
+/// Returns whether anything was removed.
+fn remove_invalid_avatar_ids(data: &mut SaveData) -> bool {
+    let before = data.avatar_ids.len() + data.avatar_defaults.len();
+    data.avatar_ids.retain(|id, _| is_valid_avatar_id(id));
+    data.avatar_defaults.retain(|id, _| is_valid_avatar_id(id));
+    let removed = before != data.avatar_ids.len() + data.avatar_defaults.len();
+    if removed {
+        log::warn!("Removed save data stored under a blank avatar id");
+    }
+    removed
+}
+
+fn child_counter(value: u8) {
+    if value > get_child_count() {
+        update_conception_for_count(value);
+        set_child_count(value);
+    }
+}
+/// What a new child count means for the conception time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConceptionChange {
+    Start,
+    Clear,
+    Keep,
+}
+/// A pregnancy starts once the count reaches `min_children` and ends when it falls below it.
+/// A scheduled start keeps its time; `start_scheduled_if_due` decides once it passes.
+fn conception_change(count: u8, min_children: u8, phase: PregnancyPhase) -> ConceptionChange {
+    let enough = count >= min_children.max(1);
+    match phase {
+        PregnancyPhase::NotPregnant if enough => ConceptionChange::Start,
+        PregnancyPhase::Active if !enough => ConceptionChange::Clear,
+        _ => ConceptionChange::Keep,
+    }
+}
+/// Starts or ends the pregnancy for a child count that is about to be set.
+/// Call before `set_child_count` so an ending pregnancy is still recorded with its children.
+fn update_conception_for_count(count: u8) {
+    let phase = pregnancy_phase(&get_child_data().unwrap_or_default(), Local::now());
+    match conception_change(count, get_min_children(), phase) {
+        ConceptionChange::Start => {
+            set_conception_time(Local::now());
+            send_is_pregnant(true);
+        }
+        ConceptionChange::Clear => {
+            record_if_completed();
+            clear_conception_time();
+            send_is_pregnant(false);
+        }
+        ConceptionChange::Keep => {}
+    }
+}
+
+fn get_system_active() -> Option<bool> {
+    *SystemActive.lock().unwrap()
+}
+
+fn set_system_active(value: bool) {
+    *SystemActive.lock().unwrap() = Some(value);
+}
+/// Whether the current avatar has a fertility system that is marked as not managed.
+fn is_unmanaged_detected() -> bool {
+    *UnmanagedDetected.lock().unwrap()
+}
+fn set_unmanaged_detected(value: bool) {
+    *UnmanagedDetected.lock().unwrap() = value;
+}
+/// Marks whether the app manages `avatar_id`, then checks the current avatar again so the
+/// change applies right away. The state of an avatar being released is saved first.
+fn set_avatar_managed(avatar_id: &str, managed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !managed && get_current_avatar().as_deref() == Some(avatar_id) {
+        save_data()?;
+    }
+    let mut data = read_data()?;
+    if managed {
+        data.unmanaged_avatars.remove(avatar_id);
+    } else {
+        data.unmanaged_avatars.insert(avatar_id.to_string());
+    }
+    save_data_writer(&data)?;
+    info!("Avatar {} is now {}", avatar_id, if managed { "managed" } else { "not managed" });
+    check_avatar_oscquery()
+}
+/// Whether the in-memory child data has changes that are not on disk yet.
+fn is_save_dirty() -> bool {
+    *SaveDirty.lock().unwrap()
+}
+fn set_save_dirty(value: bool) {
+    *SaveDirty.lock().unwrap() = value;
+}
+fn settings_path() -> std::path::PathBuf {
+    get_save_path().join("settings.json")
+}
+fn load_settings() -> Settings {
+    settings::load(&settings_path())
+}
+/// Changes one stored preference.
+fn update_settings(change: impl FnOnce(&mut Settings)) {
+    let mut stored = load_settings();
+    change(&mut stored);
+    if let Err(e) = settings::save(&settings_path(), &stored) {
+        log::error!("Failed to save settings: {}", e);
+    }
+}
+/// Applies stored preferences over the `.env` values.
+fn apply_settings(stored: &Settings) {
+    if let Some(secs) = stored.send_interval_secs {
+        set_send_interval(std::time::Duration::from_secs_f32(secs.max(MIN_SEND_INTERVAL_SECS)));
+    }
+    if let Some(receive_only) = stored.receive_only {
+        OscServer::set_receive_only(receive_only);
+    }
+    if let Some(units) = stored.display_units {
+        set_display_units(units);
+    }
+    if let Some(min_children) = stored.min_children_to_start {
+        set_min_children(min_children);
+    }
+    if let Some(cap) = stored.overdue_growth_cap {
+        set_overdue_cap(cap);
+    }
+    if let Some(addr) = stored.vrc_address {
+        OscServer::set_vrc_override(Some(addr));
+    }
+    #[cfg(feature = "discord")]
+    if let Some(enabled) = stored.discord_presence {
+        discord_presence::set_enabled(enabled);
+    }
+    #[cfg(feature = "local_api")]
+    if let Some(enabled) = stored.local_api {
+        local_api::set_enabled(enabled, current_state_json);
+    }
+    #[cfg(feature = "notifications")]
+    {
+        if let Some(enabled) = stored.notifications {
+            notifications::set_enabled(enabled);
+        }
+        let mut stored = stored.clone();
+        for milestone in notifications::milestones() {
+            if let Some(enabled) = *milestone_setting(&mut stored, milestone) {
+                notifications::set_milestone_enabled(milestone, enabled);
+            }
+        }
+    }
+}
+/// The stored setting for one notification milestone.
+#[cfg(feature = "notifications")]
+fn milestone_setting(stored: &mut Settings, milestone: notifications::Milestone) -> &mut Option<bool> {
+    match milestone {
+        notifications::Milestone::SecondTrimester => &mut stored.notify_second_trimester,
+        notifications::Milestone::ThirdTrimester => &mut stored.notify_third_trimester,
+        notifications::Milestone::AlmostDue => &mut stored.notify_almost_due,
+        notifications::Milestone::Birth => &mut stored.notify_birth,
+    }
+}
+fn get_overdue_cap() -> f64 {
+    *OverdueCap.lock().unwrap()
+}
+fn set_overdue_cap(value: f64) {
+    *OverdueCap.lock().unwrap() = value.max(1.0);
+}
+fn get_min_children() -> u8 {
+    *MinChildren.lock().unwrap()
+}
+fn set_min_children(value: u8) {
+    *MinChildren.lock().unwrap() = value.max(1);
+}
+fn get_display_units() -> usize {
+    *DisplayUnits.lock().unwrap()
+}
+fn set_display_units(value: usize) {
+    *DisplayUnits.lock().unwrap() = value;
+}
+fn get_current_avatar() -> Option<String> {
+    CurrentAvatar.lock().unwrap().clone()
+}
+/// Switches the current avatar. Unsaved changes of the previous avatar are written under its
+/// own id first, then its child data is dropped in the same step as the id changes, so a
+/// save running meanwhile never stores one avatar's data under another avatar's id.
+fn switch_avatar(value: Option<String>) {
+    if get_current_avatar() == value {
+        return;
+    }
+    autosave_tick();
+    // Always CurrentAvatar before ChildData, like `current_avatar_and_child`.
+    let mut avatar = CurrentAvatar.lock().unwrap();
+    let mut child = ChildData.lock().unwrap();
+    set_system_active(false);
+    *child = None;
+    *avatar = value;
+}
+/// The current avatar id and its child data, read as one consistent pair.
+fn current_avatar_and_child() -> (Option<String>, Option<ChildInfo>) {
+    let avatar = CurrentAvatar.lock().unwrap();
+    let child = ChildData.lock().unwrap();
+    (avatar.clone(), child.clone())
+}
+fn get_child_data() -> Option<ChildInfo> {
+    ChildData.lock().unwrap().clone()
+}
+fn set_child_data(value: ChildInfo) {
+    #[cfg(debug_assertions)]
+    assert_invariants(&value);
+    let mut lock = ChildData.lock().unwrap();
+    *lock = Some(value);
+}
+fn clear_child_data() {
+    let mut lock = ChildData.lock().unwrap();
+    *lock = None;
+}
+fn get_child_count() -> u8 {
+    let childdata: ChildInfo = get_child_data().unwrap_or_default();
+    childdata.number_of_childern
+}
+fn set_child_count(value: u8) {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        resize_conceptions(childdata, value, Local::now(), *ConcurrentPregnancies);
+        childdata.number_of_childern = value;
+        set_save_dirty(true);
+    }
+    OscServer::send_osc_data(param_address(CHILD_COUNT), vec![OscType::Int(value.into())]);
+}
+fn get_conception_time() -> Option<DateTime<Local>> {
+    let childdata: ChildInfo = get_child_data().unwrap_or_default();
+    childdata.conception_time
+}
+fn clear_conception_time() {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        childdata.conception_time = None;
+        childdata.scheduled = false;
+        childdata.additional_conceptions.clear();
+        set_save_dirty(true);
+    }
+}
+fn set_conception_time(value: DateTime<Local>) {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        childdata.conception_time = Some(value);
+        childdata.scheduled = false;
+        childdata.additional_conceptions.clear();
+        set_save_dirty(true);
+    }
+}
+fn get_gestation_time() -> f32 {
+    let childdata: ChildInfo = get_child_data().unwrap_or_default();
+    childdata.gestation_time
+}
+fn set_gestation_time(value: f32) {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        childdata.gestation_time = value;
+        set_save_dirty(true);
+    }
+}
+fn get_gestation_type() -> GestationType {
+    let childdata: ChildInfo = get_child_data().unwrap_or_default();
+    childdata.gestation
+}
+fn set_gestation_type(value: u8) {
+    let mut lock = ChildData.lock().unwrap();
+    if let Some(ref mut childdata) = *lock {
+        childdata.gestation = GestationType::try_from(value).unwrap_or(GestationType::Hours);
+        set_save_dirty(true);
+    }
+}
+/// Calculates a future DateTime by adding a duration (in whole seconds)
+/// computed as multiplier * (seconds per unit).
+#[inline] // Hint to inline the function
+pub fn calculate_future_time() -> DateTime<Local> {
+    let total_duration_secs =
+        get_gestation_time() as f64 * get_gestation_type().seconds_per_unit() as f64;
+    let conception_time = get_conception_time();
+    if conception_time.is_none() {
+        return Local::now();
+    }
+    add_gestation(conception_time.unwrap(), total_duration_secs)
+}
+/// Latest date we report as a due date, used when the real one is out of range.
+fn far_future() -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(9999, 12, 31, 23, 59, 59)
+        .earliest()
+        .unwrap_or_else(Local::now)
+}
+/// Adds `total_duration_secs` to `conception` without overflowing chrono's range.
+/// Durations that cannot be represented saturate to `far_future`.
+fn add_gestation(conception: DateTime<Local>, total_duration_secs: f64) -> DateTime<Local> {
+    // Casting directly from f64 to i64 truncates the fractional part (and saturates at i64::MAX).
+    let due = if total_duration_secs.is_finite() {
+        Duration::try_seconds(total_duration_secs as i64)
+            .and_then(|duration| conception.checked_add_signed(duration))
+    } else {
+        None
+    };
+    due.unwrap_or_else(|| {
+        log::warn!(
+            "Gestation of {} seconds is out of range, capping the due date",
+            total_duration_secs
+        );
+        far_future()
+    })
+}
+/// Returns the remaining percentage of gestation time as a decimal between 0.0 and 1.0
+/// For staggered pregnancies this is the progress of the child that will be ready first.
+#[inline] // Hint to inline the function
+pub fn get_gestation_progress_fraction() -> f64 {
+    let child = get_child_data().unwrap_or_default();
+    next_child_ready(&child_schedule(
+        &child_conception_times(&child),
+        gestation_duration_secs(&child),
+        Local::now(),
+    ))
+    .map_or(0f64, |next| next.progress)
+}
+/// Moves the conception time back so the current pregnancy is exactly at its due date.
+fn advance_to_due_date() {
+    let child = get_child_data().unwrap_or_default();
+    // Round up so whole-second elapsed time never lands just short of 1.0.
+    let total_secs = gestation_duration_secs(&child).ceil() as i64;
+    match Duration::try_seconds(total_secs).and_then(|total| Local::now().checked_sub_signed(total)) {
+        Some(conception) => set_conception_time(conception),
+        None => log::warn!("Gestation is too long to advance to its due date"),
+    }
+}
+/// Progress of a single child within the current pregnancy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ChildProgress {
+    /// Position of the child in conception order, starting at 0.
+    index: usize,
+    progress: f64,
+    due_date: DateTime<Local>,
+}
+/// Total gestation length in seconds for the given settings.
+fn gestation_duration_secs(child: &ChildInfo) -> f64 {
+    child.gestation_time as f64 * child.gestation.seconds_per_unit() as f64
+}
+/// Returns the conception time of every child, in conception order.
+/// The first child uses `conception_time`, later ones their entry in `additional_conceptions`
+/// or, without one, the shared `conception_time`.
+fn child_conception_times(child: &ChildInfo) -> Vec<DateTime<Local>> {
+    let Some(conception) = child.conception_time else {
+        return Vec::new();
+    };
+    (0..child.number_of_childern as usize)
+        .map(|index| match index {
+            0 => conception,
+            _ => child
+                .additional_conceptions
+                .get(index - 1)
+                .copied()
+                .unwrap_or(conception),
+        })
+        .collect()
+}
+/// Updates the per-child conception times before the child count changes to `count`.
+/// With `concurrent` set, children added to an ongoing pregnancy are conceived at `now`;
+/// otherwise they share `conception_time`. Removing children drops the latest ones first.
+fn resize_conceptions(child: &mut ChildInfo, count: u8, now: DateTime<Local>, concurrent: bool) {
+    let Some(conception) = child.conception_time else {
+        child.additional_conceptions.clear();
+        return;
+    };
+    let (old, count) = (child.number_of_childern as usize, count as usize);
+    if concurrent && !child.scheduled && old > 0 && count > old {
+        child.additional_conceptions.resize(old - 1, conception);
+        child.additional_conceptions.resize(count - 1, now);
+    } else {
+        child.additional_conceptions.truncate(count.saturating_sub(1));
+    }
+}
+/// Average progress over all children, or 0 without any.
+fn aggregate_progress(schedule: &[ChildProgress]) -> f64 {
+    if schedule.is_empty() {
+        return 0.0;
+    }
+    schedule.iter().map(|entry| entry.progress).sum::<f64>() / schedule.len() as f64
+}
+/// Builds the per-child progress table sorted by due date, soonest first.
+/// Children due at the same moment keep their conception order.
+fn child_schedule(
+    conceptions: &[DateTime<Local>],
+    total_duration_secs: f64,
+    now: DateTime<Local>,
+) -> Vec<ChildProgress> {
+    let mut schedule: Vec<ChildProgress> = conceptions
+        .iter()
+        .enumerate()
+        .map(|(index, conception)| {
+            let elapsed_secs = (now - *conception).num_seconds() as f64;
+            ChildProgress {
+                index,
+                progress: (elapsed_secs / total_duration_secs).clamp(0.0, 1.0),
+                due_date: add_gestation(*conception, total_duration_secs),
+            }
+        })
+        .collect();
+    schedule.sort_by(|a, b| a.due_date.cmp(&b.due_date).then(a.index.cmp(&b.index)));
+    schedule
+}
+/// Picks the child that will be ready first from a schedule built by `child_schedule`.
+fn next_child_ready(schedule: &[ChildProgress]) -> Option<ChildProgress> {
+    schedule.first().copied()
+}
+/// Snapshot of the monitor state shared with diagnostics and external tools.
+fn current_state_json() -> serde_json::Value {
+    let child = get_child_data();
+    let schedule = child.as_ref().map_or_else(Vec::new, |child| {
+        child_schedule(&child_conception_times(child), gestation_duration_secs(child), Local::now())
+    });
+    serde_json::json!({
+        "avatar_id": get_current_avatar(),
+        "system_active": get_system_active().unwrap_or(false),
+        "child": child,
+        "child_count": child.as_ref().map_or(0, |child| child.number_of_childern),
+        "progress": get_gestation_progress_fraction(),
+        "aggregate_progress": aggregate_progress(&schedule),
+        "children": schedule
+            .iter()
+            .map(|entry| serde_json::json!({
+                "child": entry.index + 1,
+                "progress": entry.progress,
+                "due_date": entry.due_date.to_rfc3339(),
+            }))
+            .collect::<Vec<_>>(),
+        "due_date": child
+            .as_ref()
+            .and_then(|child| child.conception_time)
+            .map(|_| calculate_future_time().to_rfc3339()),
+    })
+}
+fn diagnostics_report() -> String {
+    let state = serde_json::to_string_pretty(&current_state_json()).unwrap_or_default();
+    diagnostics::build_diagnostics(
+        &state,
+        &diagnostics::recent_osc(),
+        &diagnostics::avatar_swaps(),
+        &metrics::snapshot(),
+        &diagnostics::config_snapshot(),
+    )
+}
+/// Outcome of one self-test step.
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub passed: bool,
+}
+/// Walks a pregnancy through its whole lifecycle with the real handlers and reports each step.
+/// Uses a temporary save directory and sends nothing, but replaces the loaded avatar,
+/// so run it before anything else starts.
+pub fn run_selftest() -> Vec<SelfTestStep> {
+    let dir = std::env::temp_dir().join(format!("toysosc-selftest-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    set_save_path_override(Some(dir.clone()));
+    let receive_only = OscServer::is_receive_only();
+    OscServer::set_receive_only(true);
+    let steps = selftest_steps();
+    OscServer::set_receive_only(receive_only);
+    set_save_path_override(None);
+    let _ = std::fs::remove_dir_all(&dir);
+    steps
+}
+fn selftest_steps() -> Vec<SelfTestStep> {
+    let mut steps = Vec::new();
+    let mut check = |name, passed| steps.push(SelfTestStep { name, passed });
+    let avatar_id = "avtr_selftest";
+    set_min_children(1);
+    switch_avatar(Some(avatar_id.to_string()));
+    set_child_data(new_child_info(&SaveData::default(), avatar_id));
+    set_system_active(true);
+    let phase = || pregnancy_phase(&get_child_data().unwrap_or_default(), Local::now());
+    check("Avatar activates without a pregnancy", phase() == PregnancyPhase::NotPregnant);
+
+    child_counter(2);
+    check(
+        "Adding children starts the pregnancy",
+        phase() == PregnancyPhase::Active && get_gestation_progress_fraction() < 0.01,
+    );
+
+    advance_to_due_date();
+    check("Progress reaches 100% at the due date", get_gestation_progress_fraction() == 1.0);
+
+    give_birth();
+    let history = read_data().map(|data| data.history).unwrap_or_default();
+    check(
+        "Birth is recorded in the history",
+        history.len() == 1
+            && history[0].avatar_id == avatar_id
+            && history[0].number_of_childern == 2
+            && get_child_count() == 0,
+    );
+
+    child_counter(1);
+    let reset = reset_all_avatars().is_ok();
+    let saved = read_data().ok().and_then(|mut data| data.avatar_ids.remove(avatar_id));
+    check(
+        "Reset clears the pregnancy",
+        reset
+            && phase() == PregnancyPhase::NotPregnant
+            && saved.is_some_and(|child| pregnancy_phase(&child, Local::now()) == PregnancyPhase::NotPregnant),
+    );
+
+    set_gestation_time(12.5);
+    let written = matches!(save_data(), Ok(true));
+    let loaded = read_data().ok().and_then(|mut data| data.avatar_ids.remove(avatar_id));
+    check(
+        "Save data round-trips",
+        written && loaded.is_some() && loaded == get_child_data(),
+    );
+    steps
+}
+/// What the monitor is currently able to do, shown when no child data is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MonitorStatus {
+    /// VRChat has not been found or no avatar has been reported yet.
+    WaitingForVrchat,
+    /// An avatar is loaded but it has no `PregnancySave` parameter.
+    NoFertilitySystem,
+    /// The avatar has a fertility system but is marked as not managed.
+    Unmanaged,
+    Active,
+}
+fn monitor_status(connected: bool, avatar_known: bool, active: bool, unmanaged: bool) -> MonitorStatus {
+    if active {
+        MonitorStatus::Active
+    } else if unmanaged {
+        MonitorStatus::Unmanaged
+    } else if connected && avatar_known {
+        MonitorStatus::NoFertilitySystem
+    } else {
+        MonitorStatus::WaitingForVrchat
+    }
+}
+#[derive(Default)]
+pub struct PregUI {
+    last_content_size: egui::Vec2,
+    /// Shows testing helpers such as "Advance to Due Date" (set `DEBUG=true`).
+    debug_tools: bool,
+    /// Text typed into the manual VRChat address field.
+    vrc_address_input: String,
+    vrc_address_error: Option<String>,
+    /// Set while the "Reset All Avatars" confirmation is shown.
+    confirm_reset_all: bool,
+    /// Set while the "Re-anchor to Now" confirmation is shown.
+    confirm_reanchor: bool,
+    /// Shown when a gestation unit change was refused.
+    gestation_warning: Option<String>,
+    /// Position of the progress preview slider.
+    preview_progress: f64,
+    /// Delay entered for "Schedule Start", in hours.
+    schedule_in_hours: f32,
+    /// Stats from the history log and when they were computed.
+    stats: Option<(Instant, Stats)>,
+}
+
+impl PregUI {
+    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+        // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
+        // Restore app state using cc.storage (requires the "persistence" feature).
+        // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
+        // for e.g. egui::PaintCallback.
+        apply_settings(&load_settings());
+        Self {
+            debug_tools: env_or("DEBUG", false),
+            ..Self::default()
+        }
+    }
+}
+/// Formats a chrono::Duration into a human-readable string like:
 /// "2 months, 3 days, 4 hours, 5 minutes, 6 seconds"
-fn format_duration_human(dur: chrono::Duration) -> String {
+pub fn format_duration_human(dur: chrono::Duration) -> String {
+    format_duration_units(dur, usize::MAX)
+}
+/// Like `format_duration_human`, but keeps only the `max_units` largest non-zero units,
+/// e.g. "2 months, 3 days" with `max_units` of 2.
+pub fn format_duration_units(dur: chrono::Duration, max_units: usize) -> String {
+    format_duration_in(dur, max_units, *ActiveCalendar)
+}
+/// Formats `dur` using the month and year lengths of `calendar`.
+pub fn format_duration_in(dur: chrono::Duration, max_units: usize, calendar: Calendar) -> String {
     let mut secs = dur.num_seconds().max(0);
 
-    let months = secs / 2_592_000; // 30 days per month
-    secs %= 2_592_000;
+    let years = secs / calendar.year_secs;
+    secs %= calendar.year_secs;
+
+    let months = secs / calendar.month_secs;
+    secs %= calendar.month_secs;
+
+    let days = secs / 86_400;
+    secs %= 86_400;
+
+    let hours = secs / 3600;
+    secs %= 3600;
+
+    let minutes = secs / 60;
+    secs %= 60;
+
+    let mut parts = Vec::new();
+
+    if years > 0 {
+        parts.push(format!("{} year{}", years, if years != 1 { "s" } else { "" }));
+    }
+    if months > 0 {
+        parts.push(format!(
+            "{} month{}",
+            months,
+            if months != 1 { "s" } else { "" }
+        ));
+    }
+    if days > 0 {
+        parts.push(format!("{} day{}", days, if days != 1 { "s" } else { "" }));
+    }
+    if hours > 0 {
+        parts.push(format!(
+            "{} hour{}",
+            hours,
+            if hours != 1 { "s" } else { "" }
+        ));
+    }
+    if minutes > 0 {
+        parts.push(format!(
+            "{} minute{}",
+            minutes,
+            if minutes != 1 { "s" } else { "" }
+        ));
+    }
+    if secs > 0 || parts.is_empty() {
+        parts.push(format!(
+            "{} second{}",
+            secs,
+            if secs != 1 { "s" } else { "" }
+        ));
+    }
+
+    parts.truncate(max_units.max(1));
+    parts.join(", ")
+}
+/// Formats a remaining time with the precision chosen in settings.
+fn format_remaining(dur: chrono::Duration) -> String {
+    match get_display_units() {
+        0 => format_duration_human(dur),
+        units => format_duration_units(dur, units),
+    }
+}
+impl EguiApp for PregUI {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        flush_save();
+    }
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        let mut current_content_size = egui::vec2(0.0, 0.0);
+
+        let child_data = get_child_data();
+        let active = get_system_active().unwrap_or(false);
+        let avatar_id = match get_osc_query_cache().get_avatar_id() {
+            Ok(Some(id)) => id,
+            _ => "Unknown".to_string(),
+        };
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Pregnancy Monitor");
+                let (color, text) = match OscServer::connection_state() {
+                    ConnectionState::Searching => (egui::Color32::GRAY, "Searching for VRChat"),
+                    ConnectionState::OscFound => (egui::Color32::YELLOW, "OSC found"),
+                    ConnectionState::OscQueryConnected => (egui::Color32::GREEN, "Connected"),
+                    ConnectionState::Disconnected => (egui::Color32::RED, "Disconnected"),
+                };
+                ui.colored_label(color, "●").on_hover_text(text);
+                ui.label(text);
+            });
+            if OscServer::is_udp_fallback() {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    "OSCQuery unavailable, using plain UDP OSC",
+                );
+            }
+            ui.label(format!("System Active: {}", active));
+            ui.label(format!("Avatar ID: {}", avatar_id));
+            if active
+                && ui
+                    .button("Stop Managing This Avatar")
+                    .on_hover_text("Leave this avatar's fertility system to another controller.")
+                    .clicked()
+            {
+                if let Err(e) = set_avatar_managed(&avatar_id, false) {
+                    log::error!("Failed to disable avatar {}: {}", avatar_id, e);
+                }
+            }
+
+            if let Some(child) = child_data {
+                if pregnancy_phase(&child, Local::now()) == PregnancyPhase::Scheduled {
+                    if let Some(start) = child.conception_time {
+                        ui.label(format!(
+                            "Pregnancy starts in {}",
+                            format_remaining(start - Local::now())
+                        ));
+                    }
+                }
+                if child.number_of_childern > 0 {
+                    let progress = get_gestation_progress_fraction();
+                    let remaining = if let Some(conception) = child.conception_time {
+                        let future = add_gestation(conception, gestation_duration_secs(&child));
+                        let now = chrono::Local::now();
+                        let remaining = future.signed_duration_since(now);
+                        format_remaining(remaining)
+                    } else {
+                        "N/A".into()
+                    };
+                    if let Some(drift) = conception_drift(&child, Local::now()) {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            match drift {
+                                ClockDrift::FutureDated => "Conception time is in the future. Was the clock wrong?",
+                                ClockDrift::Ancient => "Conception time is implausibly old. Was the clock wrong?",
+                            },
+                        );
+                        ui.horizontal(|ui| {
+                            if !self.confirm_reanchor {
+                                if ui.button("Re-anchor to Now...").clicked() {
+                                    self.confirm_reanchor = true;
+                                }
+                            } else {
+                                ui.label("Restart this pregnancy from now?");
+                                if ui.button("Yes, re-anchor").clicked() {
+                                    set_conception_time(Local::now());
+                                    if let Err(e) = save_data() {
+                                        log::error!("Failed to save re-anchored pregnancy: {}", e);
+                                    }
+                                    self.confirm_reanchor = false;
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    self.confirm_reanchor = false;
+                                }
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Estimated Date: {} Remaining Time: {}",
+                            calculate_future_time().format("%m/%d/%Y %H:%M"),
+                            remaining
+                        ));
+                        if ui.button("Reset Pregancy.").clicked() {
+                            set_conception_time(Local::now());
+                            save_data().unwrap();
+                        }
+                        if ui
+                            .button("Give Birth")
+                            .on_hover_text("Ends the pregnancy and records it in the stats.")
+                            .clicked()
+                        {
+                            give_birth();
+                        }
+                        if self.debug_tools && ui.button("Advance to Due Date").clicked() {
+                            advance_to_due_date();
+                            save_data().unwrap();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Gestation Progress:");
+                        ui.add(egui::ProgressBar::new(progress as f32)
+                            .text(format!("{:.1}%", progress * 100.0)).show_percentage().animate(false));
+                        if child.conception_time.is_some() && calculate_future_time() < Local::now() {
+                            ui.colored_label(egui::Color32::YELLOW, "Overdue");
+                        }
+                    });
+                    egui::CollapsingHeader::new("Children")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            let schedule = child_schedule(
+                                &child_conception_times(&child),
+                                gestation_duration_secs(&child),
+                                Local::now(),
+                            );
+                            egui::Grid::new("child_schedule").striped(true).show(ui, |ui| {
+                                for entry in &schedule {
+                                    ui.label(format!("Child {}", entry.index + 1));
+                                    ui.add(
+                                        egui::ProgressBar::new(entry.progress as f32)
+                                            .desired_width(120.0)
+                                            .show_percentage()
+                                            .animate(false),
+                                    );
+                                    ui.label(format!(
+                                        "Due: {}",
+                                        entry.due_date.format("%m/%d/%Y %H:%M")
+                                    ));
+                                    ui.end_row();
+                                }
+                            });
+                            if schedule.len() > 1 {
+                                ui.label(format!(
+                                    "Overall: {:.1}%",
+                                    aggregate_progress(&schedule) * 100.0
+                                ));
+                            }
+                        });
+                    
+
+                    //ui.label(format!("Gestation Time: {:.2}", child.gestation_time));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Gestation Type:");
+                    egui::ComboBox::new("Gestation", "")
+                        .selected_text(child.gestation.to_string())
+                        .show_ui(ui, |ui| {
+                            for ty in GestationType::iter() {
+                                if ui
+                                    .selectable_label(child.gestation == ty, ty.to_string())
+                                    .clicked()
+                                {
+                                    match convert_gestation_time(child.gestation_time, child.gestation, ty) {
+                                        Some(new_time) => {
+                                            set_gestation_time(new_time);
+                                            set_gestation_type(ty as u8);
+                                            self.gestation_warning = None;
+                                        }
+                                        None => {
+                                            self.gestation_warning = Some(format!(
+                                                "{} {} is too short to express in {}",
+                                                child.gestation_time, child.gestation, ty
+                                            ));
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    if let Some(warning) = &self.gestation_warning {
+                        ui.colored_label(egui::Color32::YELLOW, warning);
+                    }
+                });
+                //Gestation Time
+                ui.horizontal(|ui| {
+                    ui.label("Gestation Time:");
+
+                    // === DragValue (syncs with gestation_time) ===
+                    let mut temp_value = child.gestation_time;
+                    let gestation_response = ui.add(
+                        egui::DragValue::new(&mut temp_value)
+                            .range(MIN_GESTATION_TIME..=f32::INFINITY)
+                            .speed(0.1)
+                            .suffix(format!(" {}", child.gestation)),
+                    );
+
+                    if gestation_response.changed() {
+                        set_gestation_time(temp_value);
+                        save_data().unwrap();
+                    }
+                    if ui
+                        .button("Use for New Avatars")
+                        .on_hover_text("New avatars start with this gestation time and type.")
+                        .clicked()
+                    {
+                        save_default_gestation(GestationDefaults {
+                            gestation_time: child.gestation_time,
+                            gestation: child.gestation,
+                        })
+                        .unwrap();
+                    }
+                    /* // === Text input ===
+                    let text_response = ui.add_sized(
+                        [80.0, 20.0],
+                        egui::TextEdit::singleline(&mut self.gestation_time_input),
+                    );
+                    if text_response.lost_focus()
+                        && ui.input(|i| {
+                            i.key_pressed(egui::Key::Enter) || i.pointer.any_released()
+                        })
+                    {
+                        if let Ok(parsed) = self.gestation_time_input.trim().parse::<f32>() {
+                            if parsed > 0.0 {
+                                set_gestation_time(parsed);
+                                save_data().unwrap();
+                            } else {
+                                println!("Value must be > 0");
+                            }
+                        } else {
+                            println!("Invalid float input");
+                        }
+                    } */
+                });
+                ui.label(format!("Child Count: {}", child.number_of_childern));
+                ui.horizontal(|ui| {
+                    // Handlers
+                    if ui.button("Add Child").clicked() || ctx.input(|i| i.key_pressed(Key::Plus)) {
+                        // Run this logic only when there's a user action
+                        let child_count = get_child_count();
+                        if child_count < 12 {
+                            update_conception_for_count(child_count + 1);
+                            set_child_count(child_count + 1);
+                            save_data().unwrap();
+                        }
+                    }
+                    if ui.button("Remove Child").clicked()
+                        || ctx.input(|i| i.key_pressed(Key::Minus))
+                    {
+                        let child_count = get_child_count();
+                        if child_count != 0 {
+                            update_conception_for_count(child_count - 1);
+                            set_child_count(child_count - 1);
+                            save_data().unwrap();
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Start In (hours):");
+                    ui.add(egui::DragValue::new(&mut self.schedule_in_hours).speed(0.1).range(0.0..=8760.0));
+                    let idle = pregnancy_phase(&child, Local::now()) != PregnancyPhase::Active;
+                    if ui
+                        .add_enabled(idle, egui::Button::new("Schedule Start"))
+                        .on_disabled_hover_text("A pregnancy is already running.")
+                        .clicked()
+                    {
+                        let delay = Duration::try_seconds((self.schedule_in_hours as f64 * 3600.0) as i64)
+                            .unwrap_or_else(Duration::zero);
+                        schedule_conception(Local::now() + delay);
+                        if let Err(e) = save_data() {
+                            log::error!("Failed to save scheduled pregnancy: {}", e);
+                        }
+                    }
+                });
+            } else {
+                match monitor_status(
+                    OscServer::get_osc_query().is_some(),
+                    get_current_avatar().is_some(),
+                    active,
+                    is_unmanaged_detected(),
+                ) {
+                    MonitorStatus::WaitingForVrchat => {
+                        ui.label("Waiting for VRChat...");
+                    }
+                    MonitorStatus::NoFertilitySystem => {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            "Current avatar has no supported fertility system",
+                        );
+                    }
+                    MonitorStatus::Unmanaged => {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(egui::Color32::YELLOW, "Fertility system found, management disabled");
+                            if ui.button("Manage This Avatar").clicked() {
+                                if let Err(e) = set_avatar_managed(&avatar_id, true) {
+                                    log::error!("Failed to enable avatar {}: {}", avatar_id, e);
+                                }
+                            }
+                        });
+                    }
+                    MonitorStatus::Active => {
+                        ui.label("No Child Data Available");
+                    }
+                }
+            }
+
+            egui::CollapsingHeader::new("Stats")
+                .default_open(false)
+                .show(ui, |ui| {
+                    // Reread the history now and then rather than on every frame.
+                    if self
+                        .stats
+                        .as_ref()
+                        .is_none_or(|(at, _)| at.elapsed() >= std::time::Duration::from_secs(5))
+                    {
+                        match read_data() {
+                            Ok(data) => self.stats = Some((Instant::now(), compute_stats(&data.history))),
+                            Err(e) => log::error!("Failed to read pregnancy history: {}", e),
+                        }
+                    }
+                    let stats = self.stats.as_ref().map(|(_, stats)| stats.clone()).unwrap_or_default();
+                    ui.label(format!("Pregnancies Completed: {}", stats.pregnancies));
+                    ui.label(format!("Children Born: {}", stats.children));
+                    ui.label(format!(
+                        "Average Gestation: {}",
+                        stats.average_gestation.map_or("N/A".into(), format_duration_human)
+                    ));
+                    ui.label(format!(
+                        "Last Birth: {}",
+                        stats
+                            .last_birth
+                            .map_or("N/A".into(), |date| date.format("%m/%d/%Y %H:%M").to_string())
+                    ));
+                });
+            egui::CollapsingHeader::new("Avatar Swaps")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let swaps = diagnostics::avatar_swaps();
+                    if swaps.is_empty() {
+                        ui.label("No avatar swaps recorded yet");
+                    }
+                    for swap in swaps.iter().rev().take(10) {
+                        ui.label(swap.to_string());
+                    }
+                });
+            egui::CollapsingHeader::new("Settings")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Send Interval:");
+                        let mut interval_secs = get_send_interval().as_secs_f32();
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut interval_secs)
+                                    .range(MIN_SEND_INTERVAL_SECS..=300.0)
+                                    .speed(0.1)
+                                    .suffix(" s"),
+                            )
+                            .changed()
+                        {
+                            set_send_interval(std::time::Duration::from_secs_f32(interval_secs));
+                            update_settings(|stored| stored.send_interval_secs = Some(interval_secs));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Remaining Time Precision:");
+                        let units_text = |units: usize| match units {
+                            0 => "All units".to_string(),
+                            1 => "1 unit".to_string(),
+                            units => format!("{} units", units),
+                        };
+                        let mut units = get_display_units();
+                        egui::ComboBox::new("display_units", "")
+                            .selected_text(units_text(units))
+                            .show_ui(ui, |ui| {
+                                for option in 0..=4 {
+                                    ui.selectable_value(&mut units, option, units_text(option));
+                                }
+                            });
+                        if units != get_display_units() {
+                            set_display_units(units);
+                            update_settings(|stored| stored.display_units = Some(units));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Children To Start:");
+                        let mut min_children = get_min_children();
+                        if ui
+                            .add(egui::DragValue::new(&mut min_children).range(1..=12))
+                            .on_hover_text("How many children are needed before a pregnancy starts.")
+                            .changed()
+                        {
+                            set_min_children(min_children);
+                            update_settings(|stored| stored.min_children_to_start = Some(min_children));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Overdue Growth Cap:");
+                        let mut cap = get_overdue_cap();
+                        if ui
+                            .add(egui::DragValue::new(&mut cap).range(1.0..=2.0).speed(0.01))
+                            .on_hover_text(
+                                "Largest PregnancySave value sent past the due date. 1.0 stops growing at the due date.",
+                            )
+                            .changed()
+                        {
+                            set_overdue_cap(cap);
+                            update_settings(|stored| stored.overdue_growth_cap = Some(cap));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Preview Progress:");
+                        let response = ui
+                            .add(egui::Slider::new(&mut self.preview_progress, 0.0..=1.0))
+                            .on_hover_text(
+                                "Hold and drag to send a test PregnancySave value. The real progress resumes on release.",
+                            );
+                        if response.is_pointer_button_down_on() || response.dragged() {
+                            if response.changed() || get_progress_preview().is_none() {
+                                set_progress_preview(self.preview_progress);
+                            }
+                        } else {
+                            end_progress_preview();
+                        }
+                        if get_progress_preview().is_some() {
+                            ui.colored_label(egui::Color32::YELLOW, "Previewing");
+                        }
+                    });
+                    let mut receive_only = OscServer::is_receive_only();
+                    if ui
+                        .checkbox(&mut receive_only, "Receive Only")
+                        .on_hover_text("Track the avatar without sending any parameters back.")
+                        .changed()
+                    {
+                        OscServer::set_receive_only(receive_only);
+                        update_settings(|stored| stored.receive_only = Some(receive_only));
+                    }
+                    #[cfg(feature = "discord")]
+                    {
+                        let mut discord = discord_presence::is_enabled();
+                        if ui.checkbox(&mut discord, "Discord Rich Presence").changed() {
+                            discord_presence::set_enabled(discord);
+                            update_settings(|stored| stored.discord_presence = Some(discord));
+                        }
+                    }
+                    #[cfg(feature = "local_api")]
+                    {
+                        let mut api = local_api::is_enabled();
+                        if ui.checkbox(&mut api, "Local Status API").changed() {
+                            local_api::set_enabled(api, current_state_json);
+                            update_settings(|stored| stored.local_api = Some(api));
+                        }
+                        match local_api::listener_state() {
+                            Some(Ok(port)) if api => {
+                                ui.label(format!("http://127.0.0.1:{}/status", port));
+                            }
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::RED, e);
+                            }
+                            _ => {}
+                        }
+                    }
+                    #[cfg(feature = "notifications")]
+                    {
+                        let mut notify = notifications::is_enabled();
+                        if ui.checkbox(&mut notify, "Desktop Notifications").changed() {
+                            notifications::set_enabled(notify);
+                            update_settings(|stored| stored.notifications = Some(notify));
+                        }
+                        if notify {
+                            ui.horizontal(|ui| {
+                                for milestone in notifications::milestones() {
+                                    let mut enabled = notifications::is_milestone_enabled(milestone);
+                                    if ui.checkbox(&mut enabled, milestone.label()).changed() {
+                                        notifications::set_milestone_enabled(milestone, enabled);
+                                        update_settings(|stored| {
+                                            *milestone_setting(stored, milestone) = Some(enabled)
+                                        });
+                                    }
+                                }
+                            });
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("VRChat Address:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.vrc_address_input)
+                                .hint_text(OscServer::get_vrc_address())
+                                .desired_width(140.0),
+                        );
+                        if ui.button("Apply").clicked() {
+                            match self.vrc_address_input.trim().parse::<std::net::SocketAddr>() {
+                                Ok(addr) => {
+                                    OscServer::set_vrc_override(Some(addr));
+                                    update_settings(|stored| stored.vrc_address = Some(addr));
+                                    self.vrc_address_error = None;
+                                }
+                                Err(_) => {
+                                    self.vrc_address_error =
+                                        Some("Enter an address like 127.0.0.1:9000".to_string());
+                                }
+                            }
+                        }
+                        if OscServer::get_vrc_override().is_some() && ui.button("Clear").clicked() {
+                            OscServer::set_vrc_override(None);
+                            update_settings(|stored| stored.vrc_address = None);
+                            self.vrc_address_input.clear();
+                            self.vrc_address_error = None;
+                        }
+                    });
+                    if let Some(error) = &self.vrc_address_error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                    ui.separator();
+                    if !self.confirm_reset_all {
+                        if ui.button("Reset All Avatars...").clicked() {
+                            self.confirm_reset_all = true;
+                        }
+                    } else {
+                        ui.colored_label(
+                            egui::Color32::RED,
+                            "This ends the pregnancy on every saved avatar and cannot be undone.",
+                        );
+                        ui.horizontal(|ui| {
+                            if ui.button("Yes, reset everything").clicked() {
+                                if let Err(e) = reset_all_avatars() {
+                                    log::error!("Failed to reset all avatars: {}", e);
+                                }
+                                self.confirm_reset_all = false;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_reset_all = false;
+                            }
+                        });
+                    }
+                });
+            egui::CollapsingHeader::new("Help & Instructions")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.label("This panel shows the current pregnancy status.");
+                    ui.label("• Remaining time is calculated based on gestation settings.");
+                    ui.label("• Gestation progress updates every second.");
+                    ui.label("• Click the Help button again to hide this.");
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Recheck Avatar").clicked() {
+                    if let Err(e) = check_avatar_oscquery() {
+                        log::error!("Failed to recheck avatar: {}", e);
+                    }
+                }
+                if ui
+                    .button("Copy Diagnostics")
+                    .on_hover_text("Copies a bug report to the clipboard and saves it as diagnostics.txt.")
+                    .clicked()
+                {
+                    let report = diagnostics_report();
+                    ctx.copy_text(report.clone());
+                    let path = get_save_path().join("diagnostics.txt");
+                    match std::fs::write(&path, report) {
+                        Ok(()) => info!("Diagnostics written to {}", path.display()),
+                        Err(e) => log::error!("Failed to write diagnostics: {}", e),
+                    }
+                }
+            });
+            current_content_size = ui.min_size();
+        });
+        // Check if the content size has changed significantly
+        let threshold = 30f32; // Prevent resizing for subpixel jitter
+        if (current_content_size - self.last_content_size).length_sq() > threshold * threshold {
+            let padding = egui::vec2(32.0, 32.0);
+            let new_size = current_content_size + padding;
+
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(new_size));
+            self.last_content_size = current_content_size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osc_query_cache::{FakeOscQuery, OscQueryCache};
+    use crate::utils::{lock_globals, test_dir};
+
+    fn at(hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap()
+    }
+
+    /// Parameter tree of an avatar with the fertility system.
+    const FERTILE_TREE: &str = r#"{"CONTENTS":{"PregnancySave":{"TYPE":"f"},"Childcount":{"TYPE":"i"}}}"#;
+    /// Parameter tree of an avatar without it.
+    const PLAIN_TREE: &str = r#"{"CONTENTS":{"VelocityX":{"TYPE":"f"}}}"#;
+
+    fn avatar_body(id: &str) -> String {
+        format!(r#"{{"VALUE":["{}"]}}"#, id)
+    }
+
+    /// Puts the app back into a known idle state, saving to a fresh directory named after
+    /// the test and answering OSCQuery from the returned fake. Hold the guard for the whole test.
+    fn reset_state(name: &str) -> (std::sync::MutexGuard<'static, ()>, FakeOscQuery) {
+        let guard = lock_globals();
+        set_save_path_override(Some(test_dir(name)));
+        OscServer::set_receive_only(false);
+        *CurrentAvatar.lock().unwrap() = None;
+        *ChildData.lock().unwrap() = None;
+        set_system_active(false);
+        set_unmanaged_detected(false);
+        set_save_dirty(false);
+        *LastIsPregnantSent.lock().unwrap() = None;
+        *BirthPulse.lock().unwrap() = false;
+        *ProgressPreview.lock().unwrap() = None;
+        *Pending.lock().unwrap() = None;
+        *LastResync.lock().unwrap() = ResyncDebounce::default();
+        set_min_children(1);
+        set_address_overrides(HashMap::new());
+        let fake = FakeOscQuery::default();
+        *get_osc_query_cache() = OscQueryCache::with_source(Box::new(fake.clone()));
+        OscServer::take_sent();
+        (guard, fake)
+    }
+
+    /// Loads `child` as the active avatar `avatar_id`.
+    fn activate(avatar_id: &str, child: ChildInfo) {
+        *CurrentAvatar.lock().unwrap() = Some(avatar_id.to_string());
+        set_child_data(child);
+        set_system_active(true);
+    }
+
+    /// Messages among `packets`, bundles flattened, as (address, args).
+    fn messages(packets: Vec<OscPacket>) -> Vec<(String, Vec<OscType>)> {
+        let mut out = Vec::new();
+        for packet in packets {
+            match packet {
+                OscPacket::Message(msg) => out.push((msg.addr, msg.args)),
+                OscPacket::Bundle(bundle) => out.extend(messages(bundle.content)),
+            }
+        }
+        out
+    }
+
+    /// Feeds a message to the handler as if it came from VRChat.
+    fn receive(addr: &str, args: Vec<OscType>) {
+        PregancyHandler.handle(OscPacket::Message(rosc::OscMessage {
+            addr: addr.to_string(),
+            args,
+        }));
+    }
+
+    fn sent_to(name: &str) -> Vec<Vec<OscType>> {
+        let addr = format!("{}{}", PARAMETER_PREFIX, name);
+        messages(OscServer::take_sent())
+            .into_iter()
+            .filter(|(sent, _)| *sent == addr)
+            .map(|(_, args)| args)
+            .collect()
+    }
+
+    #[test]
+    fn child_schedule_orders_by_due_date() {
+        let conceptions = [at(2), at(0), at(1)];
+        let schedule = child_schedule(&conceptions, 10.0 * 3600.0, at(5));
+        let order: Vec<usize> = schedule.iter().map(|entry| entry.index).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        assert_eq!(schedule[0].due_date, at(10));
+        assert_eq!(schedule[0].progress, 0.5);
+        assert_eq!(next_child_ready(&schedule).map(|next| next.index), Some(1));
+    }
+
+    #[test]
+    fn child_schedule_keeps_conception_order_for_equal_due_dates() {
+        let schedule = child_schedule(&[at(0), at(0), at(0)], 3600.0, at(0));
+        let order: Vec<usize> = schedule.iter().map(|entry| entry.index).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn aggregate_progress_averages_children() {
+        assert_eq!(aggregate_progress(&[]), 0.0);
+        let schedule = child_schedule(&[at(0), at(5)], 10.0 * 3600.0, at(10));
+        assert_eq!(aggregate_progress(&schedule), 0.75);
+        assert!(next_child_ready(&[]).is_none());
+    }
+
+    #[test]
+    fn poll_resyncs_when_the_avatar_changed_unannounced() {
+        let (_guard, fake) = reset_state("poll-resync");
+        fake.respond("/avatar/change", &avatar_body("avtr_b"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        activate("avtr_a", ChildInfo::default());
+        assert!(poll_avatar_change());
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_b"));
+        assert_eq!(get_system_active(), Some(true));
+        assert!(!sent_to("GestationTime").is_empty());
+    }
+
+    #[test]
+    fn poll_does_nothing_for_the_same_avatar() {
+        let (_guard, fake) = reset_state("poll-same");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"));
+        activate("avtr_a", ChildInfo::default());
+        assert!(!poll_avatar_change());
+        assert!(!fake.fetches().contains(&"/avatar/parameters".to_string()));
+        assert!(OscServer::take_sent().is_empty());
+    }
+
+    #[test]
+    fn new_avatars_use_their_own_defaults_before_the_global_ones() {
+        let mut data = SaveData {
+            default_gestation: GestationDefaults {
+                gestation_time: 3.0,
+                gestation: GestationType::Days,
+            },
+            ..SaveData::default()
+        };
+        data.avatar_defaults.insert(
+            "avtr_custom".to_string(),
+            GestationDefaults {
+                gestation_time: 2.0,
+                gestation: GestationType::Weeks,
+            },
+        );
+        let custom = new_child_info(&data, "avtr_custom");
+        assert_eq!((custom.gestation_time, custom.gestation), (2.0, GestationType::Weeks));
+        let other = new_child_info(&data, "avtr_other");
+        assert_eq!((other.gestation_time, other.gestation), (3.0, GestationType::Days));
+        assert_eq!(other.number_of_childern, 0);
+        assert!(other.conception_time.is_none());
+    }
+
+    #[test]
+    fn saved_default_gestation_applies_to_the_next_new_avatar() {
+        let (_guard, fake) = reset_state("default-gestation");
+        fake.respond("/avatar/change", &avatar_body("avtr_new"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        save_default_gestation(GestationDefaults {
+            gestation_time: 5.0,
+            gestation: GestationType::Months,
+        })
+        .unwrap();
+        check_avatar_oscquery().unwrap();
+        let child = get_child_data().unwrap();
+        assert_eq!((child.gestation_time, child.gestation), (5.0, GestationType::Months));
+    }
+
+    fn sender(send_bundle: bool) -> ProgressSender {
+        ProgressSender {
+            send_next_child: false,
+            send_bundle,
+            smooth_steps: 1,
+            last_sent: None,
+        }
+    }
+
+    #[test]
+    fn progress_bundle_has_a_fixed_order_and_types() {
+        let (_guard, _) = reset_state("bundle-order");
+        let bundle = progress_bundle(0.5, 3, false);
+        let addrs: Vec<&str> = bundle.iter().map(|msg| msg.addr.as_str()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "/avatar/parameters/PregnancySave",
+                "/avatar/parameters/PregnancyTrimester",
+                "/avatar/parameters/Childcount",
+                "/avatar/parameters/PregnancyOverdue",
+            ]
+        );
+        assert_eq!(bundle[0].args, [OscType::Float(0.5)]);
+        assert_eq!(bundle[1].args, [OscType::Int(2)]);
+        assert_eq!(bundle[2].args, [OscType::Int(3)]);
+        assert_eq!(bundle[3].args, [OscType::Bool(false)]);
+        assert_eq!((trimester(0.0), trimester(0.34), trimester(0.67), trimester(1.5)), (1, 2, 3, 3));
+    }
+
+    #[test]
+    fn tick_sends_progress_as_one_bundle_when_enabled() {
+        let (_guard, _) = reset_state("bundle-tick");
+        activate(
+            "avtr_a",
+            ChildInfo {
+                number_of_childern: 2,
+                conception_time: Some(Local::now()),
+                ..ChildInfo::default()
+            },
+        );
+        sender(true).tick();
+        let sent = OscServer::take_sent();
+        assert_eq!(sent.len(), 1);
+        let OscPacket::Bundle(bundle) = &sent[0] else {
+            panic!("expected a bundle, got {:?}", sent[0]);
+        };
+        assert_eq!(bundle.content.len(), 4);
+
+        sender(false).tick();
+        let sent = messages(OscServer::take_sent());
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "/avatar/parameters/PregnancySave");
+    }
+
+    #[test]
+    fn fertility_system_without_an_avatar_id_is_not_activated() {
+        let (_guard, fake) = reset_state("unknown-id");
+        fake.respond("/avatar/change", r#"{"VALUE":[""]}"#)
+            .respond("/avatar/parameters", FERTILE_TREE);
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_system_active(), Some(false));
+        assert!(get_child_data().is_none());
+        assert!(OscServer::take_sent().is_empty());
+    }
+
+    #[test]
+    fn unexpected_tree_shapes_do_not_activate() {
+        for tree in ["null", "[]", r#"{"CONTENTS":[]}"#, r#"{"CONTENTS":{"PregnancySave":null}}"#] {
+            let (_guard, fake) = reset_state("odd-tree");
+            fake.respond("/avatar/change", &avatar_body("avtr_a"))
+                .respond("/avatar/parameters", tree);
+            check_avatar_oscquery().unwrap();
+            let expected = tree.contains("PregnancySave");
+            assert_eq!(get_system_active(), Some(expected), "tree {}", tree);
+        }
+    }
+
+    #[test]
+    fn save_skips_without_an_avatar_or_child_data() {
+        let (_guard, _) = reset_state("save-skips");
+        assert!(!save_data().unwrap());
+        *CurrentAvatar.lock().unwrap() = Some(" ".to_string());
+        set_child_data(ChildInfo::default());
+        assert!(!save_data().unwrap());
+        *CurrentAvatar.lock().unwrap() = Some("avtr_a".to_string());
+        clear_child_data();
+        assert!(!save_data().unwrap());
+        assert!(read_data().unwrap().avatar_ids.is_empty());
+    }
+
+    #[test]
+    fn save_writes_only_when_something_changed() {
+        let (_guard, _) = reset_state("save-no-op");
+        activate("avtr_a", ChildInfo::default());
+        assert!(save_data().unwrap());
+        assert!(!save_data().unwrap());
+        set_gestation_time(12.0);
+        assert!(save_data().unwrap());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].gestation_time, 12.0);
+    }
+
+    #[test]
+    fn switching_avatars_saves_under_the_previous_id() {
+        let (_guard, _) = reset_state("save-switch");
+        activate("avtr_a", ChildInfo::default());
+        set_gestation_time(20.0);
+        switch_avatar(Some("avtr_b".to_string()));
+        assert!(get_child_data().is_none());
+        assert_eq!(get_system_active(), Some(false));
+        let saved = read_data().unwrap().avatar_ids;
+        assert_eq!(saved["avtr_a"].gestation_time, 20.0);
+        assert!(!saved.contains_key("avtr_b"));
+    }
+
+    #[test]
+    fn monitor_status_covers_every_state() {
+        use MonitorStatus::*;
+        assert_eq!(monitor_status(false, false, false, false), WaitingForVrchat);
+        assert_eq!(monitor_status(true, false, false, false), WaitingForVrchat);
+        assert_eq!(monitor_status(false, true, false, false), WaitingForVrchat);
+        assert_eq!(monitor_status(true, true, false, false), NoFertilitySystem);
+        assert_eq!(monitor_status(true, true, true, false), Active);
+        assert_eq!(monitor_status(true, true, false, true), Unmanaged);
+        assert_eq!(monitor_status(false, false, false, true), Unmanaged);
+    }
+
+    #[test]
+    fn avatar_without_the_system_is_deactivated() {
+        let (_guard, fake) = reset_state("no-system");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", PLAIN_TREE);
+        activate("avtr_a", ChildInfo::default());
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_system_active(), Some(false));
+        assert!(get_child_data().is_none());
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_a"));
+        assert!(!is_unmanaged_detected());
+    }
+
+    #[test]
+    fn advance_to_due_date_reaches_full_progress() {
+        let (_guard, _) = reset_state("advance");
+        for (gestation_time, gestation) in [
+            (8.0, GestationType::Hours),
+            (2.5, GestationType::Mins),
+            (9.0, GestationType::Months),
+            (0.3, GestationType::Weeks),
+        ] {
+            activate(
+                "avtr_a",
+                ChildInfo {
+                    gestation_time,
+                    gestation,
+                    number_of_childern: 1,
+                    conception_time: Some(Local::now()),
+                    ..ChildInfo::default()
+                },
+            );
+            advance_to_due_date();
+            assert_eq!(get_gestation_progress_fraction(), 1.0, "{} {}", gestation_time, gestation);
+            assert!(calculate_future_time() <= Local::now());
+        }
+    }
+
+    #[test]
+    fn advance_keeps_a_gestation_too_long_to_rewind() {
+        let (_guard, _) = reset_state("advance-overflow");
+        let conception = Local::now();
+        activate(
+            "avtr_a",
+            ChildInfo {
+                gestation_time: 1e30,
+                gestation: GestationType::Months,
+                number_of_childern: 1,
+                conception_time: Some(conception),
+                ..ChildInfo::default()
+            },
+        );
+        advance_to_due_date();
+        assert_eq!(get_conception_time(), Some(conception));
+    }
+
+    #[test]
+    fn autosave_writes_only_dirty_state() {
+        let (_guard, _) = reset_state("autosave");
+        activate("avtr_a", ChildInfo::default());
+        assert!(!autosave_tick());
+        assert!(read_data().unwrap().avatar_ids.is_empty());
+
+        set_child_count(2);
+        assert!(is_save_dirty());
+        assert!(autosave_tick());
+        assert!(!is_save_dirty());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].number_of_childern, 2);
+        assert!(!autosave_tick());
+    }
+
+    fn pregnant(children: u8, conception: DateTime<Local>) -> ChildInfo {
+        ChildInfo {
+            number_of_childern: children,
+            conception_time: Some(conception),
+            ..ChildInfo::default()
+        }
+    }
+
+    #[test]
+    fn external_is_pregnant_false_ends_an_active_pregnancy() {
+        let (_guard, _) = reset_state("is-pregnant-reset");
+        activate("avtr_a", pregnant(2, Local::now()));
+        receive("/avatar/parameters/IsPregnant", vec![OscType::Bool(false)]);
+        let child = get_child_data().unwrap();
+        assert_eq!(child.number_of_childern, 0);
+        assert!(child.conception_time.is_none());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].number_of_childern, 0);
+    }
+
+    #[test]
+    fn is_pregnant_false_is_ignored_unless_a_pregnancy_runs() {
+        let (_guard, _) = reset_state("is-pregnant-ignored");
+        set_min_children(3);
+        let waiting = ChildInfo {
+            number_of_childern: 2,
+            ..ChildInfo::default()
+        };
+        let scheduled = ChildInfo {
+            scheduled: true,
+            ..pregnant(1, Local::now() + Duration::hours(1))
+        };
+        for child in [waiting, scheduled] {
+            activate("avtr_a", child.clone());
+            receive("/avatar/parameters/IsPregnant", vec![OscType::Bool(false)]);
+            assert_eq!(get_child_data(), Some(child));
+        }
+        activate("avtr_a", pregnant(3, Local::now()));
+        receive("/avatar/parameters/IsPregnant", vec![OscType::Bool(true)]);
+        receive("/avatar/parameters/IsPregnant", vec![OscType::Int(0)]);
+        assert_eq!(get_child_count(), 3);
+    }
+
+    #[test]
+    fn echo_of_our_own_is_pregnant_is_ignored() {
+        let (_guard, _) = reset_state("is-pregnant-echo");
+        activate("avtr_a", pregnant(1, Local::now()));
+        *LastIsPregnantSent.lock().unwrap() = Some((false, Instant::now()));
+        receive("/avatar/parameters/IsPregnant", vec![OscType::Bool(false)]);
+        assert_eq!(get_child_count(), 1);
+        let now = Instant::now();
+        assert!(is_echo(Some((false, now)), false, now));
+        assert!(!is_echo(Some((true, now)), false, now));
+        assert!(!is_echo(Some((false, now)), false, now + ECHO_WINDOW));
+        assert!(!is_echo(None, false, now));
+    }
+
+    #[test]
+    fn shortening_the_interval_wakes_a_waiting_sender() {
+        let (_guard, _) = reset_state("interval-wake");
+        let previous = get_send_interval();
+        set_send_interval(std::time::Duration::from_secs(60));
+        let start = Instant::now();
+        let waiter = std::thread::spawn(|| wait_send_interval(1));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        set_send_interval(std::time::Duration::from_millis(200));
+        waiter.join().unwrap();
+        let waited = start.elapsed();
+        assert!(waited >= std::time::Duration::from_millis(200), "{:?}", waited);
+        assert!(waited < std::time::Duration::from_secs(5), "{:?}", waited);
+        set_send_interval(previous);
+    }
+
+    #[test]
+    fn smoothing_steps_divide_the_interval() {
+        let (_guard, _) = reset_state("interval-steps");
+        let previous = get_send_interval();
+        set_send_interval(std::time::Duration::from_millis(400));
+        let start = Instant::now();
+        wait_send_interval(4);
+        let waited = start.elapsed();
+        assert!(waited >= std::time::Duration::from_millis(100) && waited < std::time::Duration::from_millis(400));
+        set_send_interval(previous);
+    }
+
+    #[test]
+    fn add_gestation_saturates_instead_of_overflowing() {
+        assert_eq!(add_gestation(at(0), 3600.0), at(1));
+        assert_eq!(add_gestation(at(0), 1.9), at(0) + Duration::seconds(1));
+        for secs in [1e30, f64::MAX, f64::INFINITY, f64::NAN] {
+            assert_eq!(add_gestation(at(0), secs), far_future(), "{}", secs);
+        }
+    }
+
+    #[test]
+    fn huge_gestation_keeps_the_due_date_in_range() {
+        let (_guard, _) = reset_state("future-overflow");
+        activate(
+            "avtr_a",
+            ChildInfo {
+                gestation_time: f32::MAX,
+                gestation: GestationType::Months,
+                ..pregnant(1, Local::now())
+            },
+        );
+        assert_eq!(calculate_future_time(), far_future());
+        assert_eq!(get_gestation_progress_fraction(), 0.0);
+    }
+
+    #[test]
+    fn reset_all_clears_every_avatar_but_keeps_settings() {
+        let (_guard, _) = reset_state("reset-all");
+        let mut data = read_data().unwrap();
+        let other = ChildInfo {
+            gestation_time: 4.0,
+            gestation: GestationType::Days,
+            scheduled: true,
+            additional_conceptions: vec![at(1)],
+            ..pregnant(2, at(0))
+        };
+        data.avatar_ids.insert("avtr_b".to_string(), other);
+        data.history.push(PregnancyRecord {
+            avatar_id: "avtr_b".to_string(),
+            conception_time: at(0),
+            birth_time: at(8),
+            number_of_childern: 1,
+        });
+        save_data_writer(&data).unwrap();
+        activate("avtr_a", pregnant(1, Local::now()));
+
+        reset_all_avatars().unwrap();
+        assert_eq!(get_child_count(), 0);
+        assert!(get_conception_time().is_none());
+        assert_eq!(sent_to("IsPregnant"), [vec![OscType::Bool(false)]]);
+        let data = read_data().unwrap();
+        let other = &data.avatar_ids["avtr_b"];
+        assert_eq!((other.number_of_childern, other.conception_time, other.scheduled), (0, None, false));
+        assert!(other.additional_conceptions.is_empty());
+        assert_eq!((other.gestation_time, other.gestation), (4.0, GestationType::Days));
+        assert_eq!(data.history.len(), 1);
+    }
+
+    #[test]
+    fn remaining_label_reads_naturally() {
+        let (_guard, _) = reset_state("label");
+        set_display_units(0);
+        assert_eq!(remaining_label(Duration::days(2) + Duration::hours(3)), "Due in 2 days, 3 hours");
+        assert_eq!(remaining_label(Duration::minutes(-5)), "Overdue by 5 minutes");
+        assert_eq!(remaining_label(Duration::zero()), "Due now");
+        assert_eq!(remaining_label(Duration::hours(1)), "Due in 1 hour");
+    }
+
+    #[test]
+    fn blank_avatar_ids_are_dropped() {
+        assert!(is_valid_avatar_id("avtr_a"));
+        assert!(!is_valid_avatar_id(""));
+        assert!(!is_valid_avatar_id(" \t"));
+        let mut data = SaveData::default();
+        data.avatar_ids.insert(String::new(), ChildInfo::default());
+        data.avatar_ids.insert("avtr_a".to_string(), ChildInfo::default());
+        data.avatar_defaults.insert("  ".to_string(), GestationDefaults::default());
+        assert!(remove_invalid_avatar_ids(&mut data));
+        assert_eq!(data.avatar_ids.keys().collect::<Vec<_>>(), ["avtr_a"]);
+        assert!(data.avatar_defaults.is_empty());
+        assert!(!remove_invalid_avatar_ids(&mut data));
+    }
+
+    #[test]
+    fn blank_ids_in_the_save_file_are_cleaned_on_read() {
+        let (_guard, _) = reset_state("blank-ids");
+        let mut data = SaveData::default();
+        data.avatar_ids.insert(" ".to_string(), ChildInfo::default());
+        save_data_writer(&data).unwrap();
+        assert!(read_data().unwrap().avatar_ids.is_empty());
+        let on_disk = std::fs::read_to_string(get_save_path().join("save_data.json")).unwrap();
+        assert!(!on_disk.contains("\" \""));
+    }
+
+    #[test]
+    fn smoothing_steps_up_but_never_overshoots() {
+        assert_eq!(smooth_progress(0.0, 0.5, 0.02), 0.02);
+        assert_eq!(smooth_progress(0.49, 0.5, 0.02), 0.5);
+        assert_eq!(smooth_progress(0.8, 0.1, 0.02), 0.1);
+        assert_eq!(smooth_progress(0.5, -0.3, 0.02), 0.0);
+        assert_eq!(smooth_progress(1.0, 1.5, 0.02), 1.02);
+        let mut value = 0.0;
+        for _ in 0..100 {
+            value = smooth_progress(value, 1.0, MAX_SMOOTH_STEP);
+            assert!((0.0..=1.0).contains(&value));
+        }
+        assert_eq!(value, 1.0);
+    }
+
+    #[test]
+    fn smoothed_ticks_send_bounded_steps() {
+        let (_guard, _) = reset_state("smoothing");
+        activate("avtr_a", pregnant(1, Local::now() - Duration::hours(4)));
+        let mut sender = ProgressSender {
+            smooth_steps: 4,
+            ..sender(false)
+        };
+        let mut sent = Vec::new();
+        for _ in 0..3 {
+            sender.tick();
+            for args in sent_to("PregnancySave") {
+                let [OscType::Float(value)] = args[..] else {
+                    panic!("unexpected {:?}", args);
+                };
+                sent.push(value as f64);
+            }
+        }
+        // The first tick jumps to the real value; later ones move at most one step.
+        assert!((sent[0] - 0.5).abs() < 0.01, "{:?}", sent);
+        for pair in sent.windows(2) {
+            assert!((pair[1] - pair[0]).abs() <= MAX_SMOOTH_STEP + 1e-6, "{:?}", sent);
+        }
+    }
+
+    #[test]
+    fn identical_child_data_is_not_rewritten() {
+        assert!(child_info_changed(None, &ChildInfo::default()));
+        assert!(!child_info_changed(Some(&ChildInfo::default()), &ChildInfo::default()));
+        assert!(child_info_changed(Some(&ChildInfo::default()), &pregnant(1, at(0))));
+
+        let (_guard, _) = reset_state("identical-save");
+        activate("avtr_a", pregnant(1, at(0)));
+        assert!(save_data().unwrap());
+        // A rewrite would drop this unknown field, so it shows whether the file was touched.
+        let path = get_save_path().join("save_data.json");
+        let marked = std::fs::read_to_string(&path).unwrap().replacen('{', "{\"marker\": 1,", 1);
+        std::fs::write(&path, &marked).unwrap();
+        assert!(!save_data().unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), marked);
+        assert!(!is_save_dirty());
+    }
+
+    #[test]
+    fn gestation_accepts_ints_floats_and_clamps() {
+        assert_eq!(gestation_from_osc(&OscType::Int(2)), Some(GestationType::Weeks));
+        assert_eq!(gestation_from_osc(&OscType::Long(2)), Some(GestationType::Weeks));
+        assert_eq!(gestation_from_osc(&OscType::Float(2.0)), Some(GestationType::Weeks));
+        assert_eq!(gestation_from_osc(&OscType::Double(1.6)), Some(GestationType::Weeks));
+        assert_eq!(gestation_from_osc(&OscType::Int(9)), Some(GestationType::Mins));
+        assert_eq!(gestation_from_osc(&OscType::Int(-3)), Some(GestationType::Hours));
+        assert_eq!(gestation_from_osc(&OscType::Float(f32::NAN)), None);
+        assert_eq!(gestation_from_osc(&OscType::Double(f64::INFINITY)), None);
+        assert_eq!(gestation_from_osc(&OscType::String("2".to_string())), None);
+        assert_eq!(gestation_from_osc(&OscType::Bool(true)), None);
+    }
+
+    #[test]
+    fn gestation_time_must_be_a_positive_number() {
+        assert_eq!(gestation_time_from_osc(&OscType::Float(4.5)), Some(4.5));
+        assert_eq!(gestation_time_from_osc(&OscType::Int(3)), Some(3.0));
+        assert_eq!(gestation_time_from_osc(&OscType::Double(2.0)), Some(2.0));
+        assert_eq!(gestation_time_from_osc(&OscType::Float(0.0)), None);
+        assert_eq!(gestation_time_from_osc(&OscType::Int(-2)), None);
+        assert_eq!(gestation_time_from_osc(&OscType::Float(f32::NAN)), None);
+        assert_eq!(gestation_time_from_osc(&OscType::Float(f32::INFINITY)), None);
+        assert_eq!(gestation_time_from_osc(&OscType::String("4".to_string())), None);
+    }
+
+    #[test]
+    fn invalid_gestation_times_are_ignored() {
+        let (_guard, _) = reset_state("gestation-time-input");
+        activate("avtr_a", ChildInfo::default());
+        receive("/avatar/parameters/GestationTime", vec![OscType::Float(6.0)]);
+        assert_eq!(get_gestation_time(), 6.0);
+        receive("/avatar/parameters/GestationTime", vec![OscType::Float(-1.0)]);
+        receive("/avatar/parameters/GestationTime", vec![OscType::String("abc".to_string())]);
+        receive("/avatar/parameters/GestationTime", vec![]);
+        assert_eq!(get_gestation_time(), 6.0);
+    }
+
+    #[test]
+    fn gestation_messages_update_the_type() {
+        let (_guard, _) = reset_state("gestation-input");
+        activate("avtr_a", ChildInfo::default());
+        receive("/avatar/parameters/Gestation", vec![OscType::Int(2)]);
+        assert_eq!(get_gestation_type(), GestationType::Weeks);
+        receive("/avatar/parameters/Gestation", vec![OscType::Float(1.0)]);
+        assert_eq!(get_gestation_type(), GestationType::Days);
+        receive("/avatar/parameters/Gestation", vec![OscType::Int(40)]);
+        assert_eq!(get_gestation_type(), GestationType::Mins);
+        receive("/avatar/parameters/Gestation", vec![OscType::String("x".to_string())]);
+        assert_eq!(get_gestation_type(), GestationType::Mins);
+    }
+
+    #[test]
+    fn avatar_change_ignores_freshly_cached_data() {
+        let (_guard, fake) = reset_state("change-force-clear");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/change", &avatar_body("avtr_b"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        // Cached moments ago, well inside the debounced clear's grace window.
+        get_osc_query_cache().get_avatar_id().unwrap();
+        get_osc_query_cache().get_avatar_parameters().unwrap();
+
+        receive("/avatar/change", vec![OscType::String("avtr_b".to_string())]);
+        std::thread::sleep(*AvatarChangeWindow + std::time::Duration::from_millis(300));
+        assert_eq!(CurrentAvatar.lock().unwrap().as_deref(), Some("avtr_b"));
+        let refetched = fake.fetches().iter().filter(|f| *f == "/avatar/change").count();
+        assert_eq!(refetched, 2);
+    }
+
+    fn record(avatar_id: &str, conceived: u32, born: u32, children: u8) -> PregnancyRecord {
+        PregnancyRecord {
+            avatar_id: avatar_id.to_string(),
+            conception_time: at(conceived),
+            birth_time: at(born),
+            number_of_childern: children,
+        }
+    }
+
+    #[test]
+    fn stats_of_an_empty_history_are_zero() {
+        assert_eq!(compute_stats(&[]), Stats::default());
+    }
+
+    #[test]
+    fn stats_total_and_average_the_history() {
+        let history = [
+            record("avtr_a", 0, 2, 1),
+            record("avtr_b", 3, 9, 2),
+            record("avtr_a", 10, 11, 3),
+        ];
+        let stats = compute_stats(&history);
+        assert_eq!(stats.pregnancies, 3);
+        assert_eq!(stats.children, 6);
+        assert_eq!(stats.average_gestation, Some(Duration::hours(3)));
+        assert_eq!(stats.last_birth, Some(at(11)));
+    }
+
+    #[test]
+    fn stats_last_birth_is_the_latest_not_the_last_recorded() {
+        let history = [record("avtr_a", 5, 20, 255), record("avtr_b", 0, 1, 255)];
+        let stats = compute_stats(&history);
+        assert_eq!(stats.children, 510);
+        assert_eq!(stats.last_birth, Some(at(20)));
+    }
+
+    fn scheduled(children: u8, start: DateTime<Local>) -> ChildInfo {
+        ChildInfo {
+            scheduled: true,
+            ..pregnant(children, start)
+        }
+    }
+
+    #[test]
+    fn phases_follow_the_schedule_and_children() {
+        let now = at(12);
+        assert_eq!(pregnancy_phase(&ChildInfo::default(), now), PregnancyPhase::NotPregnant);
+        assert_eq!(pregnancy_phase(&scheduled(0, at(13)), now), PregnancyPhase::Scheduled);
+        assert_eq!(pregnancy_phase(&scheduled(2, at(13)), now), PregnancyPhase::Scheduled);
+        // Once the start passes a scheduled pregnancy is active, or not pregnant without children.
+        assert_eq!(pregnancy_phase(&scheduled(2, at(11)), now), PregnancyPhase::Active);
+        assert_eq!(pregnancy_phase(&scheduled(0, at(11)), now), PregnancyPhase::NotPregnant);
+        assert_eq!(pregnancy_phase(&pregnant(1, at(11)), now), PregnancyPhase::Active);
+        assert_eq!(pregnancy_phase(&pregnant(0, at(11)), now), PregnancyPhase::NotPregnant);
+    }
+
+    #[test]
+    fn conception_changes_by_phase() {
+        use ConceptionChange::*;
+        use PregnancyPhase::*;
+        let cases = [
+            (1, 1, NotPregnant, Start),
+            (0, 1, NotPregnant, Keep),
+            (1, 2, NotPregnant, Keep),
+            (0, 0, NotPregnant, Keep),
+            (3, 1, Scheduled, Keep),
+            (0, 1, Scheduled, Keep),
+            (2, 1, Active, Keep),
+            (1, 2, Active, Clear),
+            (0, 1, Active, Clear),
+        ];
+        for (count, min, phase, expected) in cases {
+            assert_eq!(conception_change(count, min, phase), expected, "{} of {} while {:?}", count, min, phase);
+        }
+    }
+
+    #[test]
+    fn a_due_schedule_starts_with_enough_children() {
+        let (_guard, _) = reset_state("schedule-start");
+        activate("avtr_a", scheduled(2, at(10)));
+        assert!(start_scheduled_if_due(at(11)));
+        let child = get_child_data().unwrap();
+        assert!(!child.scheduled);
+        assert_eq!(child.conception_time, Some(at(10)));
+        assert_eq!(sent_to("IsPregnant"), [[OscType::Bool(true)]]);
+    }
+
+    #[test]
+    fn a_due_schedule_lapses_without_enough_children() {
+        let (_guard, _) = reset_state("schedule-lapse");
+        set_min_children(2);
+        activate("avtr_a", scheduled(1, at(10)));
+        assert!(!start_scheduled_if_due(at(9)));
+        assert!(start_scheduled_if_due(at(11)));
+        let child = get_child_data().unwrap();
+        assert!(!child.scheduled && child.conception_time.is_none());
+        assert!(sent_to("IsPregnant").is_empty());
+    }
+
+    #[test]
+    fn scheduling_is_ignored_while_pregnant() {
+        let (_guard, _) = reset_state("schedule-ignored");
+        let conceived = Local::now() - Duration::hours(1);
+        activate("avtr_a", pregnant(1, conceived));
+        schedule_conception(Local::now() + Duration::hours(1));
+        let child = get_child_data().unwrap();
+        assert_eq!(child.conception_time, Some(conceived));
+        assert!(!child.scheduled);
+
+        set_child_data(ChildInfo::default());
+        let start = Local::now() + Duration::hours(1);
+        schedule_conception(start);
+        assert_eq!(get_child_data().unwrap().conception_time, Some(start));
+        assert!(get_child_data().unwrap().scheduled);
+    }
+
+    #[test]
+    fn each_avatar_routes_through_its_own_addresses() {
+        let (_guard, fake) = reset_state("per-avatar-routing");
+        let overrides = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(name, addr)| (name.to_string(), addr.to_string())).collect()
+        };
+        let mut data = SaveData::default();
+        data.address_overrides.insert(
+            "avtr_a".to_string(),
+            overrides(&[
+                ("PregnancySave", "/avatar/parameters/Womb/Progress"),
+                (CHILD_COUNT, "/avatar/parameters/Womb/Count"),
+            ]),
+        );
+        data.address_overrides
+            .insert("avtr_b".to_string(), overrides(&[(CHILD_COUNT, "/avatar/parameters/Kids")]));
+        save_data_writer(&data).unwrap();
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/change", &avatar_body("avtr_b"))
+            .respond("/avatar/parameters", r#"{"CONTENTS":{"Womb":{"CONTENTS":{"Progress":{"TYPE":"f"}}}}}"#)
+            .respond("/avatar/parameters", FERTILE_TREE);
+
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_a"));
+        assert_eq!(get_system_active(), Some(true));
+        receive("/avatar/parameters/Womb/Count", vec![OscType::Int(2)]);
+        receive("/avatar/parameters/Childcount", vec![OscType::Int(3)]);
+        assert_eq!(get_child_count(), 2);
+        OscServer::take_sent();
+        send_progress(0.5);
+        assert_eq!(
+            messages(OscServer::take_sent()),
+            [("/avatar/parameters/Womb/Progress".to_string(), vec![OscType::Float(0.5)])]
+        );
+
+        get_osc_query_cache().force_clear_avatar();
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_b"));
+        assert_eq!(get_system_active(), Some(true));
+        receive("/avatar/parameters/Womb/Count", vec![OscType::Int(4)]);
+        assert_eq!(get_child_count(), 0);
+        receive("/avatar/parameters/Kids", vec![OscType::Int(1)]);
+        assert_eq!(get_child_count(), 1);
+        OscServer::take_sent();
+        send_progress(0.25);
+        assert_eq!(sent_to("PregnancySave"), [[OscType::Float(0.25)]]);
+    }
+
+    #[test]
+    fn one_override_moves_the_child_count_both_ways() {
+        let (_guard, _) = reset_state("child-count-override");
+        activate("avtr_a", ChildInfo::default());
+        set_address_overrides(HashMap::from([(
+            CHILD_COUNT.to_string(),
+            "/avatar/parameters/Kids".to_string(),
+        )]));
+        OscServer::take_sent();
+        receive("/avatar/parameters/Kids", vec![OscType::Int(2)]);
+        assert_eq!(get_child_count(), 2);
+        let counts: Vec<_> = messages(OscServer::take_sent())
+            .into_iter()
+            .filter(|(addr, _)| addr.contains("Kids") || is_parameter(addr, CHILD_COUNT))
+            .collect();
+        assert_eq!(counts, [("/avatar/parameters/Kids".to_string(), vec![OscType::Int(2)])]);
+        receive("/avatar/parameters/Childcount", vec![OscType::Int(3)]);
+        assert_eq!(get_child_count(), 2);
+    }
+
+    #[test]
+    fn child_counts_are_read_from_numbers_only() {
+        assert_eq!(child_count_from_osc(&OscType::Int(2)), Some(2));
+        assert_eq!(child_count_from_osc(&OscType::Int(300)), Some(255));
+        assert_eq!(child_count_from_osc(&OscType::Int(-1)), Some(0));
+        assert_eq!(child_count_from_osc(&OscType::Long(i64::MAX)), Some(255));
+        assert_eq!(child_count_from_osc(&OscType::Float(2.6)), Some(3));
+        assert_eq!(child_count_from_osc(&OscType::Double(-4.0)), Some(0));
+        assert_eq!(child_count_from_osc(&OscType::Float(f32::NAN)), None);
+        assert_eq!(child_count_from_osc(&OscType::Double(f64::INFINITY)), None);
+        assert_eq!(child_count_from_osc(&OscType::Bool(true)), None);
+        assert_eq!(child_count_from_osc(&OscType::String("2".to_string())), None);
+    }
+
+    #[test]
+    fn childcount_messages_branch_on_the_value_kind() {
+        let (_guard, _) = reset_state("childcount-kinds");
+        activate("avtr_a", ChildInfo::default());
+        receive("/avatar/parameters/Childcount", vec![OscType::String("5".to_string())]);
+        assert_eq!(get_child_count(), 0);
+        receive("/avatar/parameters/Childcount", vec![OscType::Float(1.6)]);
+        assert_eq!(get_child_count(), 2);
+        receive("/avatar/parameters/Childcount", vec![OscType::Long(3)]);
+        assert_eq!(get_child_count(), 3);
+    }
+
+    #[test]
+    fn selftest_passes_without_sending_or_touching_real_saves() {
+        let (_guard, _) = reset_state("selftest");
+        let real_save = get_save_path().join("save_data.json");
+        let steps = run_selftest();
+        set_save_path_override(Some(test_dir("selftest-after")));
+        assert_eq!(steps.len(), 6);
+        for step in &steps {
+            assert!(step.passed, "self-test step failed: {}", step.name);
+        }
+        assert!(!OscServer::is_receive_only());
+        assert!(OscServer::take_sent().is_empty());
+        assert!(!real_save.exists());
+    }
+
+    #[test]
+    fn durations_keep_the_largest_units() {
+        let format = |dur: Duration, units| format_duration_in(dur, units, Calendar::SIMPLE);
+        let long = Duration::days(63) + Duration::hours(4) + Duration::seconds(7);
+        assert_eq!(format(long, usize::MAX), "2 months, 3 days, 4 hours, 7 seconds");
+        assert_eq!(format(long, 2), "2 months, 3 days");
+        assert_eq!(format(long, 1), "2 months");
+        assert_eq!(format(long, 0), "2 months");
+        // Zero units are skipped rather than taking a slot.
+        assert_eq!(format(Duration::days(60) + Duration::minutes(5) + Duration::seconds(1), 2), "2 months, 5 minutes");
+        assert_eq!(format(Duration::days(400), 2), "1 year, 1 month");
+        assert_eq!(format(Duration::seconds(75), 2), "1 minute, 15 seconds");
+        assert_eq!(format(Duration::seconds(9), 2), "9 seconds");
+        assert_eq!(format(Duration::zero(), 2), "0 seconds");
+        assert_eq!(format(Duration::seconds(-30), 2), "0 seconds");
+    }
+
+    #[test]
+    fn remaining_time_uses_the_display_units_setting() {
+        let _guard = lock_globals();
+        let previous = get_display_units();
+        let dur = Duration::hours(5) + Duration::minutes(6) + Duration::seconds(7);
+        set_display_units(2);
+        assert_eq!(format_remaining(dur), "5 hours, 6 minutes");
+        set_display_units(0);
+        assert_eq!(format_remaining(dur), "5 hours, 6 minutes, 7 seconds");
+        set_display_units(previous);
+    }
+
+    #[test]
+    fn buffered_values_keep_the_latest_valid_one() {
+        let (_guard, _) = reset_state("pending-buffer");
+        buffer_pending_value("/avatar/parameters/Childcount", Some(&OscType::Int(2)));
+        assert!(Pending.lock().unwrap().is_none(), "nothing is buffered outside an avatar change");
+
+        reset_pending_values(Some("avtr_a".to_string()));
+        buffer_pending_value("/avatar/parameters/Childcount", Some(&OscType::Int(2)));
+        buffer_pending_value("/avatar/parameters/Childcount", Some(&OscType::String("x".to_string())));
+        buffer_pending_value("/avatar/parameters/GestationTime", Some(&OscType::Float(4.5)));
+        buffer_pending_value("/avatar/parameters/GestationTime", Some(&OscType::Float(-1.0)));
+        buffer_pending_value("/avatar/parameters/Gestation", Some(&OscType::Int(2)));
+        buffer_pending_value("/avatar/parameters/Gestation", Some(&OscType::String("3".to_string())));
+        buffer_pending_value("/avatar/parameters/VelocityX", Some(&OscType::Float(1.0)));
+        assert_eq!(
+            *Pending.lock().unwrap(),
+            Some(PendingValues {
+                avatar_id: Some("avtr_a".to_string()),
+                child_count: Some(2),
+                gestation_time: Some(4.5),
+                gestation: Some(GestationType::Weeks),
+            })
+        );
+    }
+
+    #[test]
+    fn applying_buffered_values_follows_the_child_counter_rules() {
+        let pending = |count| PendingValues {
+            child_count: Some(count),
+            ..PendingValues::default()
+        };
+        let mut child = ChildInfo::default();
+        apply_pending(&mut child, pending(2), 1, at(3));
+        assert_eq!((child.number_of_childern, child.conception_time), (2, Some(at(3))));
+        // Lower counts are ignored and a running pregnancy keeps its conception time.
+        apply_pending(&mut child, pending(1), 1, at(4));
+        apply_pending(&mut child, pending(3), 1, at(5));
+        assert_eq!((child.number_of_childern, child.conception_time), (3, Some(at(3))));
+
+        let mut child = ChildInfo::default();
+        apply_pending(&mut child, pending(1), 2, at(3));
+        assert_eq!((child.number_of_childern, child.conception_time), (1, None));
+    }
+
+    #[test]
+    fn values_buffered_for_another_avatar_are_dropped() {
+        let (_guard, _) = reset_state("pending-other-avatar");
+        activate("avtr_b", ChildInfo::default());
+        *Pending.lock().unwrap() = Some(PendingValues {
+            avatar_id: Some("avtr_a".to_string()),
+            child_count: Some(2),
+            ..PendingValues::default()
+        });
+        assert!(!apply_pending_values("avtr_b"));
+        assert!(Pending.lock().unwrap().is_none());
+        assert_eq!(get_child_count(), 0);
+    }
+
+    #[test]
+    fn values_received_while_inactive_apply_on_activation() {
+        let (_guard, fake) = reset_state("pending-activation");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        begin_avatar_change(Some("avtr_a".to_string()));
+        buffer_pending_value("/avatar/parameters/Childcount", Some(&OscType::Int(2)));
+        buffer_pending_value("/avatar/parameters/Gestation", Some(&OscType::Int(3)));
+
+        check_avatar_oscquery().unwrap();
+        let child = get_child_data().unwrap();
+        assert_eq!((child.number_of_childern, child.gestation), (2, GestationType::Months));
+        assert!(child.conception_time.is_some());
+        assert!(Pending.lock().unwrap().is_none());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"], child);
+        assert_eq!(sent_to("IsPregnant"), [[OscType::Bool(true)]]);
+    }
+
+    /// Sets the child count the way the UI buttons do.
+    fn set_count(count: u8) {
+        update_conception_for_count(count);
+        set_child_count(count);
+    }
+
+    #[test]
+    fn pregnancy_starts_at_the_minimum_child_count() {
+        let (_guard, _) = reset_state("min-children-up");
+        set_min_children(2);
+        activate("avtr_a", ChildInfo::default());
+        child_counter(1);
+        assert_eq!(get_child_count(), 1);
+        assert!(get_conception_time().is_none());
+        assert!(sent_to("IsPregnant").is_empty());
+
+        child_counter(2);
+        assert!(get_conception_time().is_some());
+        assert_eq!(sent_to("IsPregnant"), [[OscType::Bool(true)]]);
+        child_counter(3);
+        assert!(sent_to("IsPregnant").is_empty());
+    }
+
+    #[test]
+    fn dropping_below_the_minimum_ends_the_pregnancy() {
+        let (_guard, _) = reset_state("min-children-down");
+        set_min_children(2);
+        activate("avtr_a", ChildInfo::default());
+        set_count(3);
+        let conceived = get_conception_time();
+        assert!(conceived.is_some());
+        set_count(2);
+        assert_eq!(get_conception_time(), conceived);
+        OscServer::take_sent();
+
+        set_count(1);
+        assert!(get_conception_time().is_none());
+        assert_eq!(sent_to("IsPregnant"), [[OscType::Bool(false)]]);
+        // Not a birth, so nothing is recorded.
+        assert!(read_data().unwrap().history.is_empty());
+
+        set_count(2);
+        assert!(get_conception_time().is_some());
+        assert_eq!(sent_to("IsPregnant"), [[OscType::Bool(true)]]);
+    }
+
+    #[test]
+    fn a_swap_logs_exactly_one_entry() {
+        let (_guard, fake) = reset_state("swap-log");
+        *CurrentAvatar.lock().unwrap() = Some("avtr_swap_from".to_string());
+        fake.respond("/avatar/change", &avatar_body("avtr_swap_to"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        let entries = || {
+            diagnostics::avatar_swaps()
+                .into_iter()
+                .filter(|swap| swap.to.as_deref() == Some("avtr_swap_to"))
+                .collect::<Vec<_>>()
+        };
+        let before = Local::now();
+        check_avatar_oscquery().unwrap();
+        let logged = entries();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].from.as_deref(), Some("avtr_swap_from"));
+        assert!(logged[0].fertility_detected);
+        assert!(logged[0].time >= before);
+
+        // Checking the same avatar again is not a swap.
+        get_osc_query_cache().force_clear_avatar();
+        check_avatar_oscquery().unwrap();
+        assert_eq!(entries().len(), 1);
+    }
+
+    #[test]
+    fn giving_birth_records_and_ends_the_pregnancy() {
+        let (_guard, _) = reset_state("give-birth");
+        let conceived = Local::now() - Duration::hours(2);
+        activate(
+            "avtr_a",
+            ChildInfo {
+                scheduled: true,
+                additional_conceptions: vec![conceived],
+                ..pregnant(3, conceived)
+            },
+        );
+        give_birth();
+        let child = get_child_data().unwrap();
+        assert_eq!((child.number_of_childern, child.conception_time, child.scheduled), (0, None, false));
+        assert!(child.additional_conceptions.is_empty());
+        assert_eq!(
+            messages(OscServer::take_sent()),
+            [
+                (format!("{}PregnancyBirth", PARAMETER_PREFIX), vec![OscType::Bool(true)]),
+                (format!("{}{}", PARAMETER_PREFIX, CHILD_COUNT), vec![OscType::Int(0)]),
+                (format!("{}IsPregnant", PARAMETER_PREFIX), vec![OscType::Bool(false)]),
+            ]
+        );
+
+        let data = read_data().unwrap();
+        assert_eq!(data.history.len(), 1);
+        let record = &data.history[0];
+        assert_eq!((record.avatar_id.as_str(), record.conception_time), ("avtr_a", conceived));
+        assert_eq!(record.number_of_childern, 3);
+        assert_eq!(data.avatar_ids["avtr_a"], child);
+
+        // The birth event is a pulse, released on the next send.
+        sender(false).tick();
+        assert_eq!(sent_to("PregnancyBirth"), [[OscType::Bool(false)]]);
+        sender(false).tick();
+        assert!(sent_to("PregnancyBirth").is_empty());
+    }
+
+    #[test]
+    fn giving_birth_without_children_does_nothing() {
+        let (_guard, _) = reset_state("give-birth-empty");
+        activate("avtr_a", ChildInfo::default());
+        give_birth();
+        assert!(OscServer::take_sent().is_empty());
+        assert!(read_data().unwrap().history.is_empty());
+    }
+
+    const LEGACY_SAVE: &str = r#"{
+        "avtr_a": {
+            "conception_time": "2024-01-01T03:00:00+00:00",
+            "gestation_time": 6.0,
+            "gestation": "Days",
+            "number_of_childern": 2
+        },
+        "avtr_b": {
+            "conception_time": null,
+            "gestation_time": 8.0,
+            "gestation": "Hours",
+            "number_of_childern": 0
+        }
+    }"#;
+
+    #[test]
+    fn legacy_saves_are_converted() {
+        let (data, migrated) = parse_save_data(LEGACY_SAVE).unwrap();
+        assert!(migrated);
+        assert_eq!(data.avatar_ids.len(), 2);
+        let a = &data.avatar_ids["avtr_a"];
+        assert_eq!(a.conception_time, Some(chrono::Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap().with_timezone(&Local)));
+        assert_eq!((a.gestation_time, a.gestation, a.number_of_childern), (6.0, GestationType::Days, 2));
+        assert!(!a.scheduled && a.additional_conceptions.is_empty());
+        assert_eq!(data.avatar_ids["avtr_b"], ChildInfo::default());
+        assert_eq!(data.default_gestation, GestationDefaults::default());
+        assert!(data.history.is_empty() && data.address_overrides.is_empty());
+    }
+
+    #[test]
+    fn current_saves_are_not_treated_as_legacy() {
+        let current = serde_json::to_string(&SaveData::default()).unwrap();
+        assert!(!parse_save_data(&current).unwrap().1);
+        assert!(!parse_save_data(r#"{"avatar_ids":{}}"#).unwrap().1);
+        assert!(parse_save_data(r#"{"avtr_a":{"unexpected":true}}"#).is_err());
+    }
+
+    #[test]
+    fn legacy_saves_are_written_back_in_the_current_format() {
+        let (_guard, _) = reset_state("legacy-import");
+        let path = get_save_path().join("save_data.json");
+        std::fs::write(&path, LEGACY_SAVE).unwrap();
+        let data = read_data().unwrap();
+        assert_eq!(data.avatar_ids["avtr_a"].number_of_childern, 2);
+        let on_disk: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(on_disk.get("avatar_ids").is_some_and(|ids| ids.get("avtr_a").is_some()));
+        assert!(on_disk.get("avtr_a").is_none());
+    }
+
+    #[test]
+    fn a_corrupt_save_is_moved_aside() {
+        let (_guard, _) = reset_state("corrupt-save");
+        let path = get_save_path().join("save_data.json");
+        std::fs::write(&path, "{\"avatar_ids\": {").unwrap();
+        assert!(read_data().unwrap().avatar_ids.is_empty());
+        assert!(!path.exists());
+        let backup = path.with_extension("json.bak");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "{\"avatar_ids\": {");
+
+        activate("avtr_a", pregnant(2, at(0)));
+        save_data().unwrap();
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].number_of_childern, 2);
+    }
+
+    #[test]
+    fn heartbeat_needs_an_active_avatar_and_a_route() {
+        use ConnectionState::*;
+        assert!(heartbeat_due(true, OscFound));
+        assert!(heartbeat_due(true, OscQueryConnected));
+        assert!(!heartbeat_due(true, Searching));
+        assert!(!heartbeat_due(true, Disconnected));
+        assert!(!heartbeat_due(false, OscQueryConnected));
+    }
+
+    #[test]
+    fn heartbeat_runs_while_active_and_ends_with_false() {
+        let (_guard, _) = reset_state("heartbeat");
+        let state = OscServer::connection_state();
+        OscServer::set_connection_state(ConnectionState::OscQueryConnected);
+        app_active_tick();
+        assert!(sent_to("PregnancyAppActive").is_empty());
+
+        activate("avtr_a", ChildInfo::default());
+        app_active_tick();
+        app_active_tick();
+        assert_eq!(sent_to("PregnancyAppActive"), [[OscType::Bool(true)], [OscType::Bool(true)]]);
+        app_active_exit();
+        assert_eq!(sent_to("PregnancyAppActive"), [[OscType::Bool(false)]]);
+
+        OscServer::set_connection_state(ConnectionState::Disconnected);
+        app_active_tick();
+        app_active_exit();
+        assert!(sent_to("PregnancyAppActive").is_empty());
+        OscServer::set_connection_state(state);
+    }
+
+    #[test]
+    fn only_the_latest_change_is_current() {
+        let changes = ChangeCoalescer::default();
+        let first = changes.begin();
+        let second = changes.begin();
+        assert!(!changes.is_current(first));
+        assert!(changes.is_current(second));
+        assert_eq!(changes.current(), second);
+    }
+
+    #[test]
+    fn rapid_avatar_changes_check_only_the_last() {
+        let (_guard, fake) = reset_state("rapid-changes");
+        fake.respond("/avatar/change", &avatar_body("avtr_c"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        for id in ["avtr_a", "avtr_b", "avtr_c"] {
+            receive("/avatar/change", vec![OscType::String(id.to_string())]);
+        }
+        std::thread::sleep(*AvatarChangeWindow + std::time::Duration::from_millis(300));
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_c"));
+        assert_eq!(get_system_active(), Some(true));
+        let checks = fake.fetches().iter().filter(|f| *f == "/avatar/change").count();
+        assert_eq!(checks, 1);
+        let data = read_data().unwrap();
+        assert_eq!(data.avatar_ids.keys().collect::<Vec<_>>(), ["avtr_c"]);
+    }
+
+    /// Reports a new avatar change while the avatar id is being fetched.
+    struct ChangeDuringFetch(FakeOscQuery);
+    impl crate::osc_query_cache::OscQuerySource for ChangeDuringFetch {
+        fn base_url(&self) -> Option<String> {
+            self.0.base_url()
+        }
+        fn fetch(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+            if url.ends_with("/avatar/change") {
+                AvatarChanges.begin();
+            }
+            self.0.fetch(url)
+        }
+    }
+
+    #[test]
+    fn a_superseded_check_changes_nothing() {
+        let (_guard, fake) = reset_state("superseded-check");
+        fake.respond("/avatar/change", &avatar_body("avtr_old"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        *get_osc_query_cache() = OscQueryCache::with_source(Box::new(ChangeDuringFetch(fake)));
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_current_avatar(), None);
+        assert_eq!(get_system_active(), Some(false));
+        assert!(read_data().unwrap().avatar_ids.is_empty());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn consistent_child_data_has_no_violations() {
+        let now = at(12);
+        let valid = [
+            ChildInfo::default(),
+            pregnant(1, at(10)),
+            ChildInfo {
+                additional_conceptions: vec![at(11)],
+                ..pregnant(2, at(10))
+            },
+            ChildInfo {
+                scheduled: true,
+                ..pregnant(0, at(13))
+            },
+            // Long overdue still reports progress within range.
+            pregnant(1, now - Duration::days(30)),
+        ];
+        for child in valid {
+            assert_eq!(check_invariants(&child, now), Vec::<String>::new(), "{:?}", child);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn inconsistent_child_data_is_flagged() {
+        let now = at(12);
+        let invalid = [
+            (pregnant(0, at(10)), "conception time is set without any children"),
+            (
+                ChildInfo {
+                    additional_conceptions: vec![at(11)],
+                    ..pregnant(1, at(10))
+                },
+                "more separate conceptions than children",
+            ),
+            (
+                ChildInfo {
+                    scheduled: true,
+                    ..ChildInfo::default()
+                },
+                "scheduled without a start time",
+            ),
+            (
+                ChildInfo {
+                    gestation_time: 0.0,
+                    ..ChildInfo::default()
+                },
+                "gestation time 0 is not positive",
+            ),
+            (
+                ChildInfo {
+                    gestation_time: f32::NAN,
+                    ..ChildInfo::default()
+                },
+                "gestation time NaN is not positive",
+            ),
+        ];
+        for (child, expected) in invalid {
+            assert_eq!(check_invariants(&child, now), [expected], "{:?}", child);
+        }
+    }
+
+    #[test]
+    fn calendars_set_month_and_year_lengths() {
+        assert_eq!(Calendar::SIMPLE, Calendar { month_secs: 30 * 86_400, year_secs: 365 * 86_400 });
+        assert_eq!(Calendar::REALISTIC.month_secs, 2_630_016);
+        assert_eq!(Calendar::REALISTIC.year_secs, 31_557_600);
+        assert_eq!(GestationType::Months.seconds_per_unit_in(Calendar::REALISTIC), 2_630_016);
+        assert_eq!(GestationType::Weeks.seconds_per_unit_in(Calendar::REALISTIC), 604_800);
+    }
+
+    #[test]
+    fn month_gestations_are_due_by_the_calendar() {
+        let due = |calendar: Calendar| {
+            add_gestation(at(0), 9.0 * GestationType::Months.seconds_per_unit_in(calendar) as f64)
+        };
+        assert_eq!(due(Calendar::SIMPLE), at(0) + Duration::days(270));
+        assert_eq!(
+            due(Calendar::REALISTIC),
+            at(0) + Duration::days(273) + Duration::hours(23) + Duration::minutes(2) + Duration::seconds(24)
+        );
+        assert_eq!(due(Calendar::from_days(31.0, 365.0)), at(0) + Duration::days(279));
+    }
+
+    #[test]
+    fn durations_are_formatted_by_the_calendar() {
+        assert_eq!(format_duration_in(Duration::days(365), 2, Calendar::SIMPLE), "1 year");
+        assert_eq!(format_duration_in(Duration::days(365), 2, Calendar::REALISTIC), "11 months, 30 days");
+        assert_eq!(format_duration_in(Duration::days(61), 2, Calendar::SIMPLE), "2 months, 1 day");
+        assert_eq!(format_duration_in(Duration::days(61), 2, Calendar::REALISTIC), "2 months, 2 hours");
+    }
+
+    #[test]
+    fn resyncs_are_debounced() {
+        let start = Instant::now();
+        let mut debounce = ResyncDebounce::default();
+        assert!(debounce.try_begin(start));
+        assert!(!debounce.try_begin(start + RESYNC_DEBOUNCE / 2));
+        assert!(!debounce.try_begin(start + RESYNC_DEBOUNCE - std::time::Duration::from_millis(1)));
+        assert!(debounce.try_begin(start + RESYNC_DEBOUNCE));
+    }
+
+    #[test]
+    fn a_connect_burst_sends_one_full_resync() {
+        let (_guard, _) = reset_state("connect-resync");
+        activate(
+            "avtr_a",
+            ChildInfo {
+                gestation_time: 4.0,
+                gestation: GestationType::Days,
+                ..pregnant(2, Local::now() - Duration::days(1))
+            },
+        );
+        for _ in 0..3 {
+            PregancyHandler.on_connect();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(2300));
+        let sent = OscServer::take_sent();
+        assert_eq!(sent.len(), 1);
+        assert!(matches!(sent[0], OscPacket::Bundle(_)));
+        let sent = messages(sent);
+        let args = |name: &str| {
+            let addr = format!("{}{}", PARAMETER_PREFIX, name);
+            sent.iter().find(|(to, _)| *to == addr).map(|(_, args)| args.clone())
+        };
+        assert_eq!(args("GestationTime"), Some(vec![OscType::Float(4.0)]));
+        assert_eq!(args("Gestation"), Some(vec![OscType::Int(GestationType::Days.into())]));
+        assert_eq!(args(CHILD_COUNT), Some(vec![OscType::Int(2)]));
+        assert_eq!(args("IsPregnant"), Some(vec![OscType::Bool(true)]));
+        match args("PregnancySave").as_deref() {
+            Some([OscType::Float(progress)]) => assert!((progress - 0.25).abs() < 0.01, "{}", progress),
+            other => panic!("no progress sent: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn state_json_matches_the_computed_status() {
+        let (_guard, _) = reset_state("state-json");
+        let idle = current_state_json();
+        assert_eq!(idle["avatar_id"], serde_json::Value::Null);
+        assert_eq!(idle["child_count"], 0);
+        assert_eq!(idle["due_date"], serde_json::Value::Null);
+
+        activate(
+            "avtr_a",
+            ChildInfo {
+                gestation_time: 4.0,
+                gestation: GestationType::Days,
+                ..pregnant(2, Local::now() - Duration::days(1))
+            },
+        );
+        let state = current_state_json();
+        assert_eq!(state["avatar_id"], "avtr_a");
+        assert_eq!(state["system_active"], true);
+        assert_eq!(state["child_count"], 2);
+        let progress = state["progress"].as_f64().unwrap();
+        assert!((progress - get_gestation_progress_fraction()).abs() < 1e-3);
+        assert!((progress - 0.25).abs() < 1e-3);
+        assert_eq!(state["due_date"], calculate_future_time().to_rfc3339());
+        assert_eq!(state["children"].as_array().unwrap().len(), 2);
+        let child: ChildInfo = serde_json::from_value(state["child"].clone()).unwrap();
+        assert_eq!(Some(child), get_child_data());
+    }
+
+    #[test]
+    fn updated_settings_survive_a_reload() {
+        let (_guard, _) = reset_state("update-settings");
+        update_settings(|stored| stored.display_units = Some(3));
+        update_settings(|stored| stored.min_children_to_start = Some(2));
+        let stored = load_settings();
+        assert_eq!((stored.display_units, stored.min_children_to_start), (Some(3), Some(2)));
 
-    let days = secs / 86_400;
-    secs %= 86_400;
+        std::fs::write(settings_path(), "not json").unwrap();
+        update_settings(|stored| stored.receive_only = Some(false));
+        assert_eq!(
+            load_settings(),
+            Settings {
+                receive_only: Some(false),
+                ..Settings::default()
+            }
+        );
+    }
 
-    let hours = secs / 3600;
-    secs %= 3600;
+    /// The single progress value sent since the last call.
+    fn sent_progress() -> f32 {
+        match sent_to("PregnancySave").as_slice() {
+            [args] => match args.as_slice() {
+                [OscType::Float(progress)] => *progress,
+                other => panic!("unexpected progress args {:?}", other),
+            },
+            other => panic!("expected one progress send, got {:?}", other),
+        }
+    }
 
-    let minutes = secs / 60;
-    secs %= 60;
+    #[test]
+    fn the_preview_overrides_progress_until_released() {
+        let (_guard, _) = reset_state("progress-preview");
+        let child = ChildInfo {
+            gestation_time: 4.0,
+            gestation: GestationType::Days,
+            ..pregnant(2, Local::now() - Duration::days(1))
+        };
+        activate("avtr_a", child.clone());
+        let mut sender = sender(false);
 
-    let mut parts = Vec::new();
+        set_progress_preview(0.8);
+        assert_eq!(sent_progress(), 0.8);
+        sender.tick();
+        assert_eq!(sent_progress(), 0.8);
+        set_progress_preview(1.5);
+        assert_eq!(sent_progress(), 1.0);
+        assert_eq!(get_child_data(), Some(child));
+        assert!(!is_save_dirty());
 
-    if months > 0 {
-        parts.push(format!(
-            "{} month{}",
-            months,
-            if months != 1 { "s" } else { "" }
-        ));
+        end_progress_preview();
+        assert!((sent_progress() - 0.25).abs() < 1e-3);
+        sender.tick();
+        assert!((sent_progress() - 0.25).abs() < 1e-3);
+        // Releasing again has nothing to restore.
+        end_progress_preview();
+        assert!(sent_to("PregnancySave").is_empty());
     }
-    if days > 0 {
-        parts.push(format!("{} day{}", days, if days != 1 { "s" } else { "" }));
+
+    #[test]
+    fn releasing_the_preview_without_a_pregnancy_sends_zero() {
+        let (_guard, _) = reset_state("progress-preview-idle");
+        set_progress_preview(0.6);
+        OscServer::take_sent();
+        end_progress_preview();
+        assert_eq!(sent_progress(), 0.0);
     }
-    if hours > 0 {
-        parts.push(format!(
-            "{} hour{}",
-            hours,
-            if hours != 1 { "s" } else { "" }
-        ));
+
+    #[test]
+    fn conversions_too_small_for_the_ui_are_refused() {
+        use GestationType::*;
+        assert_eq!(convert_gestation_time(1.0, Mins, Months), None);
+        assert_eq!(convert_gestation_time(1.0, Mins, Weeks), None);
+        assert_eq!(convert_gestation_time(0.5, Mins, Hours), None);
+        assert_eq!(convert_gestation_time(3.0, Mins, Hours), Some(0.05));
+        assert_eq!(convert_gestation_time(2.0, Weeks, Mins), Some(20_160.0));
+        assert_eq!(convert_gestation_time(2.0, Months, Mins), Some(2.0 * Months.seconds_per_unit() as f32 / 60.0));
     }
-    if minutes > 0 {
-        parts.push(format!(
-            "{} minute{}",
-            minutes,
-            if minutes != 1 { "s" } else { "" }
-        ));
+
+    #[test]
+    fn extreme_conversions_keep_the_due_date() {
+        let conception = at(0);
+        for from in GestationType::iter() {
+            for to in GestationType::iter() {
+                for time in [0.01, 0.5, 1.0, 7.0, 40.0, 1_000.0, 1_000_000.0] {
+                    let Some(converted) = convert_gestation_time(time, from, to) else {
+                        continue;
+                    };
+                    let original_secs = time as f64 * from.seconds_per_unit() as f64;
+                    let original = add_gestation(conception, original_secs);
+                    let due = add_gestation(conception, converted as f64 * to.seconds_per_unit() as f64);
+                    let drift = (due - original).num_seconds().abs() as f64;
+                    assert!(
+                        drift <= (original_secs * 1e-6).max(1.0),
+                        "{} {:?} -> {} {:?} moved the due date by {}s",
+                        time,
+                        from,
+                        converted,
+                        to,
+                        drift
+                    );
+                }
+            }
+        }
     }
-    if secs > 0 || parts.is_empty() {
-        parts.push(format!(
-            "{} second{}",
-            secs,
-            if secs != 1 { "s" } else { "" }
-        ));
+
+    #[test]
+    fn auto_reset_keeps_only_the_gestation_settings() {
+        let saved = ChildInfo {
+            gestation_time: 3.0,
+            gestation: GestationType::Weeks,
+            scheduled: true,
+            additional_conceptions: vec![at(2)],
+            ..pregnant(2, at(1))
+        };
+        let fresh = ChildInfo::default();
+        assert_eq!(child_on_load(Some(saved.clone()), fresh.clone(), false), (saved.clone(), false));
+        assert_eq!(child_on_load(None, fresh.clone(), true), (fresh.clone(), false));
+        let (reset, was_reset) = child_on_load(Some(saved), fresh, true);
+        assert!(was_reset);
+        assert_eq!(
+            reset,
+            ChildInfo {
+                gestation_time: 3.0,
+                gestation: GestationType::Weeks,
+                ..ChildInfo::default()
+            }
+        );
     }
 
-    parts.join(", ")
-}
-impl EguiApp for PregUI {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.request_repaint_after(std::time::Duration::from_secs(1));
-        let mut current_content_size = egui::vec2(0.0, 0.0);
+    #[test]
+    fn reloading_with_auto_reset_starts_from_zero() {
+        let (_guard, fake) = reset_state("auto-reset");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        let mut data = SaveData::default();
+        data.avatar_ids.insert("avtr_a".to_string(), pregnant(2, Local::now() - Duration::hours(1)));
+        save_data_writer(&data).unwrap();
 
-        let child_data = get_child_data();
-        let active = get_system_active().unwrap_or(false);
-        let avatar_id = match get_osc_query_cache().get_avatar_id() {
-            Ok(Some(id)) => id,
-            _ => "Unknown".to_string(),
-        };
+        check_avatar(true).unwrap();
+        let child = get_child_data().unwrap();
+        assert_eq!((child.number_of_childern, child.conception_time), (0, None));
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"], child);
+        assert_eq!(sent_to(CHILD_COUNT), [[OscType::Int(0)]]);
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Pregnancy Monitor");
-            ui.label(format!("System Active: {}", active));
-            ui.label(format!("Avatar ID: {}", avatar_id));
+    #[test]
+    fn checking_without_a_load_resumes_the_saved_pregnancy() {
+        let (_guard, fake) = reset_state("auto-reset-off");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        let saved = pregnant(2, Local::now() - Duration::hours(1));
+        let mut data = SaveData::default();
+        data.avatar_ids.insert("avtr_a".to_string(), saved.clone());
+        save_data_writer(&data).unwrap();
 
-            if let Some(child) = child_data {
-                if child.number_of_childern > 0 {
-                    let progress = get_gestation_progress_fraction();
-                    let remaining = if let Some(conception) = child.conception_time {
-                        let future = conception
-                            + chrono::Duration::seconds(
-                                (child.gestation_time * child.gestation.seconds_per_unit() as f32)
-                                    as i64,
-                            );
-                        let now = chrono::Local::now();
-                        let remaining = future.signed_duration_since(now);
-                        format_duration_human(remaining)
-                    } else {
-                        "N/A".into()
-                    };
-                    
-                    ui.horizontal(|ui| {
-                        ui.label(format!(
-                            "Estimated Date: {} Remaining Time: {}",
-                            calculate_future_time().format("%m/%d/%Y %H:%M"),
-                            remaining
-                        ));
-                        if ui.button("Reset Pregancy.").clicked() {
-                            set_conception_time(Local::now());
-                            save_data().unwrap();
-                        }
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label(format!("Gestation Progress:"));
-                        ui.add(egui::ProgressBar::new(progress as f32)
-                            .text(format!("{:.1}%", progress * 100.0)).show_percentage().animate(false));
-                    });
-                    
+        check_avatar(false).unwrap();
+        assert_eq!(get_child_data(), Some(saved));
+    }
 
-                    //ui.label(format!("Gestation Time: {:.2}", child.gestation_time));
-                }
-                ui.horizontal(|ui| {
-                    ui.label("Gestation Type:");
-                    egui::ComboBox::new("Gestation", "")
-                        .selected_text(child.gestation.to_string())
-                        .show_ui(ui, |ui| {
-                            for ty in GestationType::iter() {
-                                if ui
-                                    .selectable_label(child.gestation == ty, ty.to_string())
-                                    .clicked()
-                                {
-                                    let new_time = child.gestation.seconds_per_unit() as f64
-                                        * child.gestation_time as f64
-                                        / ty.seconds_per_unit() as f64;
-                                    set_gestation_time(new_time as f32);
-                                    set_gestation_type(ty as u8);
-                                }
-                            }
-                        });
-                });
-                //Gestation Time
-                ui.horizontal(|ui| {
-                    ui.label("Gestation Time:");
+    #[test]
+    fn future_dated_conceptions_are_detected() {
+        let now = at(12);
+        let future = pregnant(1, now + Duration::hours(2));
+        assert_eq!(conception_drift(&future, now), Some(ClockDrift::FutureDated));
+        // Small clock corrections and scheduled starts are expected.
+        assert_eq!(conception_drift(&pregnant(1, now + Duration::seconds(30)), now), None);
+        let scheduled = ChildInfo {
+            scheduled: true,
+            ..future
+        };
+        assert_eq!(conception_drift(&scheduled, now), None);
+        assert_eq!(conception_drift(&ChildInfo::default(), now), None);
+    }
 
-                    // === DragValue (syncs with gestation_time) ===
-                    let mut temp_value = child.gestation_time;
-                    let gestation_response = ui.add(
-                        egui::DragValue::new(&mut temp_value)
-                            .range(0.01..=f32::INFINITY)
-                            .speed(0.1)
-                            .suffix(&format!(" {}", child.gestation.to_string())),
-                    );
+    #[test]
+    fn ancient_conceptions_are_detected() {
+        let now = at(12);
+        // 8 hour gestations: a year is the floor.
+        assert_eq!(conception_drift(&pregnant(1, now - Duration::days(300)), now), None);
+        assert_eq!(conception_drift(&pregnant(1, now - Duration::days(366)), now), Some(ClockDrift::Ancient));
+        // Long gestations allow 100 times their length.
+        let months = |conceived| ChildInfo {
+            gestation_time: 9.0,
+            gestation: GestationType::Months,
+            ..pregnant(1, conceived)
+        };
+        assert_eq!(conception_drift(&months(now - Duration::days(3650)), now), None);
+        assert_eq!(conception_drift(&months(now - Duration::days(365 * 80)), now), Some(ClockDrift::Ancient));
+    }
 
-                    if gestation_response.changed() {
-                        set_gestation_time(temp_value);
-                        save_data().unwrap();
-                    }
-                    /* // === Text input ===
-                    let text_response = ui.add_sized(
-                        [80.0, 20.0],
-                        egui::TextEdit::singleline(&mut self.gestation_time_input),
-                    );
-                    if text_response.lost_focus()
-                        && ui.input(|i| {
-                            i.key_pressed(egui::Key::Enter) || i.pointer.any_released()
-                        })
-                    {
-                        if let Ok(parsed) = self.gestation_time_input.trim().parse::<f32>() {
-                            if parsed > 0.0 {
-                                set_gestation_time(parsed);
-                                save_data().unwrap();
-                            } else {
-                                println!("Value must be > 0");
-                            }
-                        } else {
-                            println!("Invalid float input");
-                        }
-                    } */
-                });
-                ui.label(format!("Child Count: {}", child.number_of_childern));
-                ui.horizontal(|ui| {
-                    // Handlers
-                    if ui.button("Add Child").clicked() || ctx.input(|i| i.key_pressed(Key::Plus)) {
-                        // Run this logic only when there's a user action
-                        let child_count = get_child_count();
-                        if get_conception_time().is_none() {
-                            set_conception_time(Local::now());
-                        }
-                        if child_count < 12 {
-                            set_child_count(child_count + 1);
-                            save_data().unwrap();
-                        }
-                    }
-                    if ui.button("Remove Child").clicked()
-                        || ctx.input(|i| i.key_pressed(Key::Minus))
-                    {
-                        let child_count = get_child_count();
-                        if child_count != 0 {
-                            if child_count == 1 {
-                                clear_conception_time();
-                            }
-                            set_child_count(child_count - 1);
-                            save_data().unwrap();
-                        }
-                    }
-                });
-            } else {
-                ui.label("No Child Data Available");
-            }
+    #[test]
+    fn drifted_conceptions_are_kept_until_the_user_agrees() {
+        let (_guard, fake) = reset_state("clock-drift");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        let future = pregnant(2, Local::now() + Duration::days(3));
+        let mut data = SaveData::default();
+        data.avatar_ids.insert("avtr_a".to_string(), future.clone());
+        save_data_writer(&data).unwrap();
 
-            egui::CollapsingHeader::new("Help & Instructions")
-                .default_open(false)
-                .show(ui, |ui| {
-                    ui.label("This panel shows the current pregnancy status.");
-                    ui.label("• Remaining time is calculated based on gestation settings.");
-                    ui.label("• Gestation progress updates every second.");
-                    ui.label("• Click the Help button again to hide this.");
-                });
-            if ui.button("Recheck Avatar").clicked() {
-                check_avatar_oscquery().unwrap();
-            }
-            current_content_size = ui.min_size();
-        });
-        // Check if the content size has changed significantly
-        let threshold = 30f32; // Prevent resizing for subpixel jitter
-        if (current_content_size - self.last_content_size).length_sq() > threshold * threshold {
-            let padding = egui::vec2(32.0, 32.0);
-            let new_size = current_content_size + padding;
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_child_data(), Some(future.clone()));
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"], future);
+    }
 
-            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(new_size));
-            self.last_content_size = current_content_size;
+    fn default_schema() -> FertilitySchema {
+        FertilitySchema {
+            overrides: HashMap::new(),
         }
     }
+
+    fn param(name: &str, args: Vec<OscType>) -> (String, Vec<OscType>) {
+        (format!("{}{}", PARAMETER_PREFIX, name), args)
+    }
+
+    #[test]
+    fn params_without_children_are_the_gestation_settings() {
+        let child = ChildInfo {
+            gestation_time: 6.0,
+            gestation: GestationType::Days,
+            ..ChildInfo::default()
+        };
+        assert_eq!(
+            params_to_send(&child, &default_schema(), at(12)),
+            [
+                param("GestationTime", vec![OscType::Float(6.0)]),
+                param("Gestation", vec![OscType::Int(GestationType::Days.into())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn params_while_pregnant_include_the_count_and_flag() {
+        let child = pregnant(2, at(10));
+        assert_eq!(
+            params_to_send(&child, &default_schema(), at(12)),
+            [
+                param("GestationTime", vec![OscType::Float(8.0)]),
+                param("Gestation", vec![OscType::Int(GestationType::Hours.into())]),
+                param(CHILD_COUNT, vec![OscType::Int(2)]),
+                param("IsPregnant", vec![OscType::Bool(true)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn params_when_overdue_include_the_overdue_flag() {
+        let child = pregnant(1, at(2));
+        let params = params_to_send(&child, &default_schema(), at(11));
+        assert_eq!(params.len(), 5);
+        assert_eq!(params[4], param("PregnancyOverdue", vec![OscType::Bool(true)]));
+        // Exactly at the due date is not overdue yet.
+        assert_eq!(params_to_send(&child, &default_schema(), at(10)).len(), 4);
+    }
+
+    #[test]
+    fn params_before_a_scheduled_start_leave_out_the_flag() {
+        let child = ChildInfo {
+            scheduled: true,
+            ..pregnant(2, at(14))
+        };
+        let params = params_to_send(&child, &default_schema(), at(12));
+        assert_eq!(params.last(), Some(&param(CHILD_COUNT, vec![OscType::Int(2)])));
+    }
+
+    #[test]
+    fn params_use_the_schema_addresses() {
+        let schema = FertilitySchema {
+            overrides: HashMap::from([("IsPregnant".to_string(), "/avatar/parameters/Womb/On".to_string())]),
+        };
+        let params = params_to_send(&pregnant(1, at(10)), &schema, at(12));
+        assert_eq!(params[3], ("/avatar/parameters/Womb/On".to_string(), vec![OscType::Bool(true)]));
+    }
+
+    /// Raises or lowers the child count like `set_child_count` at `now`.
+    fn resize(child: &mut ChildInfo, count: u8, now: DateTime<Local>, concurrent: bool) {
+        resize_conceptions(child, count, now, concurrent);
+        child.number_of_childern = count;
+    }
+
+    #[test]
+    fn overlapping_conceptions_have_their_own_due_dates() {
+        let mut child = pregnant(1, at(0));
+        resize(&mut child, 2, at(4), true);
+        assert_eq!(child_conception_times(&child), [at(0), at(4)]);
+
+        let schedule = child_schedule(&child_conception_times(&child), gestation_duration_secs(&child), at(6));
+        let dues: Vec<(usize, DateTime<Local>)> = schedule.iter().map(|entry| (entry.index, entry.due_date)).collect();
+        assert_eq!(dues, [(0, at(8)), (1, at(12))]);
+        assert_eq!(schedule[0].progress, 0.75);
+        assert_eq!(schedule[1].progress, 0.25);
+        assert_eq!(aggregate_progress(&schedule), 0.5);
+
+        // The first child is born at its due date while the second keeps growing.
+        let later = child_schedule(&child_conception_times(&child), gestation_duration_secs(&child), at(10));
+        assert_eq!((later[0].progress, later[1].progress), (1.0, 0.75));
+        assert_eq!(aggregate_progress(&later), 0.875);
+    }
+
+    #[test]
+    fn shared_mode_keeps_one_conception() {
+        let mut child = pregnant(1, at(0));
+        resize(&mut child, 3, at(4), false);
+        assert_eq!(child_conception_times(&child), [at(0), at(0), at(0)]);
+        assert!(child.additional_conceptions.is_empty());
+    }
+
+    #[test]
+    fn removing_children_drops_the_latest_conceptions() {
+        let mut child = pregnant(1, at(0));
+        resize(&mut child, 2, at(2), true);
+        resize(&mut child, 3, at(4), true);
+        assert_eq!(child_conception_times(&child), [at(0), at(2), at(4)]);
+        resize(&mut child, 2, at(5), true);
+        assert_eq!(child_conception_times(&child), [at(0), at(2)]);
+        // Adding back starts a new clock rather than restoring the old one.
+        resize(&mut child, 3, at(6), true);
+        assert_eq!(child_conception_times(&child), [at(0), at(2), at(6)]);
+        resize(&mut child, 0, at(7), true);
+        assert!(child.additional_conceptions.is_empty());
+    }
+
+    #[test]
+    fn a_change_just_before_exit_is_saved() {
+        let (_guard, _) = reset_state("exit-flush");
+        activate("avtr_a", ChildInfo::default());
+        save_data().unwrap();
+        set_gestation_time(12.5);
+        assert!(is_save_dirty());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].gestation_time, 8.0);
+
+        shutdown();
+        assert!(!is_save_dirty());
+        assert_eq!(read_data().unwrap().avatar_ids["avtr_a"].gestation_time, 12.5);
+    }
+
+    #[test]
+    fn exiting_without_changes_writes_nothing() {
+        let (_guard, _) = reset_state("exit-clean");
+        activate("avtr_a", ChildInfo::default());
+        flush_save();
+        assert!(!get_save_path().join("save_data.json").exists());
+    }
+
+    #[test]
+    fn overdue_progress_grows_up_to_the_cap() {
+        let day = 86_400.0;
+        assert_eq!(overdue_progress(0.0, 10.0 * day, 1.2), 1.0);
+        assert_eq!(overdue_progress(-day, 10.0 * day, 1.2), 1.0);
+        assert!((overdue_progress(day, 10.0 * day, 1.2) - 1.1).abs() < 1e-9);
+        assert_eq!(overdue_progress(2.0 * day, 10.0 * day, 1.2), 1.2);
+        assert_eq!(overdue_progress(50.0 * day, 10.0 * day, 1.2), 1.2);
+        // The default cap of 1.0 keeps the old behaviour.
+        assert_eq!(overdue_progress(5.0 * day, 10.0 * day, 1.0), 1.0);
+        assert_eq!(overdue_progress(day, 0.0, 1.2), 1.0);
+    }
+
+    #[test]
+    fn overdue_sends_follow_the_cap() {
+        let (_guard, _) = reset_state("overdue-send");
+        let cap = get_overdue_cap();
+        activate(
+            "avtr_a",
+            ChildInfo {
+                gestation_time: 10.0,
+                gestation: GestationType::Days,
+                ..pregnant(1, Local::now() - Duration::days(11))
+            },
+        );
+        set_overdue_cap(1.0);
+        sender(false).tick();
+        assert_eq!(sent_progress(), 1.0);
+
+        set_overdue_cap(1.2);
+        sender(false).tick();
+        assert!((sent_progress() - 1.1).abs() < 1e-3);
+        set_overdue_cap(0.5);
+        assert_eq!(get_overdue_cap(), 1.0);
+        set_overdue_cap(cap);
+    }
+
+    #[test]
+    fn unmanaged_avatars_are_detected_but_left_alone() {
+        let (_guard, fake) = reset_state("unmanaged");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        let mut data = SaveData::default();
+        data.unmanaged_avatars.insert("avtr_a".to_string());
+        save_data_writer(&data).unwrap();
+
+        check_avatar_oscquery().unwrap();
+        assert!(is_unmanaged_detected());
+        assert_eq!(get_system_active(), Some(false));
+        assert!(get_child_data().is_none());
+        assert!(OscServer::take_sent().is_empty());
+        receive("/avatar/parameters/Childcount", vec![OscType::Int(2)]);
+        assert!(!read_data().unwrap().avatar_ids.contains_key("avtr_a"));
+
+        set_avatar_managed("avtr_a", true).unwrap();
+        assert!(!is_unmanaged_detected());
+        assert_eq!(get_system_active(), Some(true));
+        assert!(read_data().unwrap().is_managed("avtr_a"));
+        assert!(!sent_to("GestationTime").is_empty());
+    }
+
+    #[test]
+    fn releasing_an_active_avatar_saves_it_first() {
+        let (_guard, fake) = reset_state("release-avatar");
+        fake.respond("/avatar/change", &avatar_body("avtr_a"))
+            .respond("/avatar/parameters", FERTILE_TREE);
+        check_avatar_oscquery().unwrap();
+        child_counter(2);
+        let child = get_child_data().unwrap();
+        OscServer::take_sent();
+
+        set_avatar_managed("avtr_a", false).unwrap();
+        assert!(is_unmanaged_detected());
+        assert_eq!(get_system_active(), Some(false));
+        assert!(OscServer::take_sent().is_empty());
+        let data = read_data().unwrap();
+        assert!(!data.is_managed("avtr_a"));
+        assert_eq!(data.avatar_ids["avtr_a"], child);
+    }
+
+    #[test]
+    fn detection_retries_until_found() {
+        let trees = [PLAIN_TREE, PLAIN_TREE, FERTILE_TREE];
+        let mut fetched = 0;
+        let detect = |tree: &serde_json::Value| tree.pointer("/CONTENTS/PregnancySave").is_some();
+        let found = retry_detection(
+            3,
+            std::time::Duration::ZERO,
+            || {
+                fetched += 1;
+                serde_json::from_str::<serde_json::Value>(trees[fetched - 1])
+            },
+            detect,
+        );
+        assert!(found.unwrap());
+        assert_eq!(fetched, 3);
+
+        let mut fetched = 0;
+        let missing = retry_detection(
+            2,
+            std::time::Duration::ZERO,
+            || {
+                fetched += 1;
+                serde_json::from_str::<serde_json::Value>(PLAIN_TREE)
+            },
+            detect,
+        );
+        assert!(!missing.unwrap());
+        assert_eq!(fetched, 2);
+
+        let failed = retry_detection(2, std::time::Duration::ZERO, || Err("offline"), detect);
+        assert_eq!(failed, Err("offline"));
+    }
+
+    #[test]
+    fn a_partial_tree_after_a_swap_is_fetched_again() {
+        let (_guard, fake) = reset_state("partial-tree");
+        *CurrentAvatar.lock().unwrap() = Some("avtr_old".to_string());
+        fake.respond("/avatar/change", &avatar_body("avtr_new"))
+            .respond("/avatar/parameters", PLAIN_TREE)
+            .respond("/avatar/parameters", FERTILE_TREE);
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_current_avatar().as_deref(), Some("avtr_new"));
+        assert_eq!(get_system_active(), Some(true));
+        let fetches = fake.fetches().iter().filter(|f| *f == "/avatar/parameters").count();
+        assert_eq!(fetches, 2);
+    }
+
+    #[test]
+    fn an_avatar_still_missing_the_system_after_retries_is_unsupported() {
+        let (_guard, fake) = reset_state("partial-tree-missing");
+        *CurrentAvatar.lock().unwrap() = Some("avtr_old".to_string());
+        fake.respond("/avatar/change", &avatar_body("avtr_new"))
+            .respond("/avatar/parameters", PLAIN_TREE);
+        check_avatar_oscquery().unwrap();
+        assert_eq!(get_system_active(), Some(false));
+        let fetches = fake.fetches().iter().filter(|f| *f == "/avatar/parameters").count();
+        assert_eq!(fetches, 1 + DETECT_RETRIES as usize);
+    }
 }
@@ -0,0 +1,105 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// UI preferences kept in `settings.json` so they survive restarts.
+/// Unset fields fall back to the `.env` value or the built-in default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub send_interval_secs: Option<f32>,
+    pub receive_only: Option<bool>,
+    pub display_units: Option<usize>,
+    pub min_children_to_start: Option<u8>,
+    pub overdue_growth_cap: Option<f64>,
+    pub discord_presence: Option<bool>,
+    pub local_api: Option<bool>,
+    pub notifications: Option<bool>,
+    pub notify_second_trimester: Option<bool>,
+    pub notify_third_trimester: Option<bool>,
+    pub notify_almost_due: Option<bool>,
+    pub notify_birth: Option<bool>,
+    /// Where to send to instead of the discovered VRChat address.
+    pub vrc_address: Option<SocketAddr>,
+}
+
+/// Reads the settings at `path`. A missing file gives the defaults, and a corrupt one
+/// is moved aside to `settings.json.bak` so the next save starts clean.
+pub fn load(path: &Path) -> Settings {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Settings::default(),
+    };
+    match serde_json::from_str(&content) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Settings file {} is corrupt, using defaults: {}", path.display(), e);
+            if let Err(e) = std::fs::rename(path, path.with_extension("json.bak")) {
+                warn!("Failed to move corrupt settings file aside: {}", e);
+            }
+            Settings::default()
+        }
+    }
+}
+pub fn save(path: &Path, settings: &Settings) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(settings).map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_dir;
+
+    #[test]
+    fn settings_round_trip() {
+        let path = test_dir("settings-round-trip").join("settings.json");
+        let settings = Settings {
+            send_interval_secs: Some(2.5),
+            receive_only: Some(true),
+            display_units: Some(2),
+            min_children_to_start: Some(3),
+            notify_birth: Some(false),
+            vrc_address: Some("192.168.1.20:9000".parse().unwrap()),
+            ..Settings::default()
+        };
+        save(&path, &settings).unwrap();
+        assert_eq!(load(&path), settings);
+    }
+
+    #[test]
+    fn a_missing_file_gives_the_defaults() {
+        let path = test_dir("settings-missing").join("settings.json");
+        assert_eq!(load(&path), Settings::default());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn missing_fields_are_unset() {
+        let path = test_dir("settings-partial").join("settings.json");
+        std::fs::write(&path, r#"{"display_units":1,"removed_setting":true}"#).unwrap();
+        let expected = Settings {
+            display_units: Some(1),
+            ..Settings::default()
+        };
+        assert_eq!(load(&path), expected);
+    }
+
+    #[test]
+    fn a_corrupt_file_is_moved_aside() {
+        let path = test_dir("settings-corrupt").join("settings.json");
+        std::fs::write(&path, "{\"display_units\": 2,").unwrap();
+        assert_eq!(load(&path), Settings::default());
+        assert!(!path.exists());
+        let backup = path.with_extension("json.bak");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "{\"display_units\": 2,");
+
+        let settings = Settings {
+            receive_only: Some(false),
+            ..Settings::default()
+        };
+        save(&path, &settings).unwrap();
+        assert_eq!(load(&path), settings);
+    }
+}
@@ -1,10 +1,44 @@
 use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+static SAVE_PATH_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
 pub fn json_path_exists(json_data: &Value, path: &str) -> bool {
     json_data.pointer(path).is_some()
 }
+/// Reads an environment variable and parses it, falling back to `default` when unset or invalid.
+pub fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+/// Points `get_save_path` somewhere else, e.g. a temporary directory for the self-test.
+pub fn set_save_path_override(path: Option<PathBuf>) {
+    *SAVE_PATH_OVERRIDE.lock().unwrap() = path;
+}
 pub fn get_save_path() -> std::path::PathBuf {
+    if let Some(path) = SAVE_PATH_OVERRIDE.lock().unwrap().clone() {
+        std::fs::create_dir_all(&path).expect("Failed to create save directory");
+        return path;
+    }
     let mut path = dirs::data_dir().expect("Failed to find app data directory");
     path.push("ToysOSC");
     std::fs::create_dir_all(&path).expect("Failed to create ToysOSC directory");
     path
-}
\ No newline at end of file
+}
+/// Fresh, empty directory under the system temp dir for one test.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("toysosc-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).expect("Failed to create test directory");
+    path
+}
+
+/// Serializes tests that touch the app-wide state. A failed test does not block the rest.
+#[cfg(test)]
+pub fn lock_globals() -> std::sync::MutexGuard<'static, ()> {
+    static GLOBALS: Mutex<()> = Mutex::new(());
+    GLOBALS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}